@@ -0,0 +1,5 @@
+-- 同一内容の画像を再利用するためのコンテンツハッシュ
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS content_hash TEXT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_uploads_user_content_hash ON uploads(user_id, content_hash);
+CREATE INDEX IF NOT EXISTS idx_uploads_content_hash ON uploads(content_hash);
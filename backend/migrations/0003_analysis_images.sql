@@ -0,0 +1,2 @@
+-- 画像を含めたAI分析のオプトイン設定
+ALTER TABLE users ADD COLUMN IF NOT EXISTS analyze_images BOOLEAN NOT NULL DEFAULT false;
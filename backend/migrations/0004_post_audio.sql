@@ -0,0 +1,2 @@
+-- 音声メモから作成した投稿の音声ファイル
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS audio_url TEXT;
@@ -0,0 +1,9 @@
+-- 気分の強さと、ユーザーごとの気分ラベル
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS mood_intensity SMALLINT;
+
+CREATE TABLE IF NOT EXISTS mood_labels (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    mood TEXT NOT NULL,
+    label TEXT NOT NULL,
+    PRIMARY KEY (user_id, mood)
+);
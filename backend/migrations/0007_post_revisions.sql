@@ -0,0 +1,22 @@
+-- 投稿の変更履歴と、分析が対象としたリビジョン
+CREATE TABLE IF NOT EXISTS post_revisions (
+    id UUID PRIMARY KEY,
+    post_id UUID NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    revision_number INTEGER NOT NULL,
+    title TEXT,
+    content TEXT NOT NULL,
+    mood TEXT,
+    mood_intensity SMALLINT,
+    image_urls JSONB NOT NULL DEFAULT '[]',
+    created_at TIMESTAMP DEFAULT NOW(),
+    UNIQUE (post_id, revision_number)
+);
+
+-- 既存の投稿の現在の内容を最初のリビジョンとして保存
+INSERT INTO post_revisions (id, post_id, user_id, revision_number, title, content, mood, mood_intensity, image_urls, created_at)
+SELECT gen_random_uuid(), p.id, p.user_id, 1, p.title, p.content, p.mood, p.mood_intensity, COALESCE(p.image_urls, '[]'), COALESCE(p.updated_at, p.created_at, NOW())
+FROM posts p
+WHERE NOT EXISTS (SELECT 1 FROM post_revisions r WHERE r.post_id = p.id);
+
+ALTER TABLE analyses ADD COLUMN IF NOT EXISTS revision_id UUID REFERENCES post_revisions(id) ON DELETE SET NULL;
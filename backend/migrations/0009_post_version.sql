@@ -0,0 +1,2 @@
+-- 楽観的排他制御のためのバージョン
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,5 @@
+-- 下書き（一覧・分析・集計から除外される）
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS status TEXT NOT NULL DEFAULT 'published'
+    CHECK (status IN ('draft', 'published'));
+
+CREATE INDEX IF NOT EXISTS idx_posts_user_status ON posts(user_id, status);
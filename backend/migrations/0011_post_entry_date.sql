@@ -0,0 +1,7 @@
+-- ユーザーが指定する日記の日付（既存の投稿は作成日時を引き継ぐ）
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS entry_date TIMESTAMPTZ;
+UPDATE posts SET entry_date = created_at AT TIME ZONE 'UTC' WHERE entry_date IS NULL;
+ALTER TABLE posts ALTER COLUMN entry_date SET DEFAULT NOW();
+ALTER TABLE posts ALTER COLUMN entry_date SET NOT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_posts_user_entry_date ON posts(user_id, entry_date DESC, id DESC);
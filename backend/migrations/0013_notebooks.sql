@@ -0,0 +1,14 @@
+-- 投稿をまとめるノート（仕事・カウンセリング・旅行など）
+CREATE TABLE IF NOT EXISTS notebooks (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    description TEXT,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    UNIQUE (user_id, name)
+);
+
+-- ノートを削除しても投稿は残す
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS notebook_id UUID REFERENCES notebooks(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_posts_notebook_id ON posts(notebook_id);
@@ -0,0 +1,13 @@
+-- 投稿本文中の [[...]] リンク
+-- タイトルで書かれたリンクは target_title を保持し、対象が見つからない・削除された場合は target_post_id が NULL になる
+CREATE TABLE IF NOT EXISTS post_links (
+    id UUID PRIMARY KEY,
+    source_post_id UUID NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    target_post_id UUID REFERENCES posts(id) ON DELETE SET NULL,
+    target_title TEXT,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_post_links_source ON post_links(source_post_id);
+CREATE INDEX IF NOT EXISTS idx_post_links_target ON post_links(target_post_id);
+CREATE INDEX IF NOT EXISTS idx_post_links_dangling_title ON post_links(lower(target_title)) WHERE target_post_id IS NULL;
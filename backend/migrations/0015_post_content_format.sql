@@ -0,0 +1,3 @@
+-- 本文の書式（サーバーでHTMLに変換する際に使用）
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS content_format TEXT NOT NULL DEFAULT 'plain'
+    CHECK (content_format IN ('plain', 'markdown'));
@@ -0,0 +1,26 @@
+-- 投稿の共有リンク（トークンはハッシュのみ保存する）
+CREATE TABLE IF NOT EXISTS share_links (
+    id UUID PRIMARY KEY,
+    post_id UUID NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    token_hash TEXT NOT NULL UNIQUE,
+    password_hash TEXT,
+    include_analysis BOOLEAN NOT NULL DEFAULT FALSE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_share_links_post_id ON share_links(post_id);
+
+-- 共有リンクの閲覧記録（パスワード不一致も記録する）
+CREATE TABLE IF NOT EXISTS share_link_views (
+    id UUID PRIMARY KEY,
+    share_link_id UUID NOT NULL REFERENCES share_links(id) ON DELETE CASCADE,
+    granted BOOLEAN NOT NULL,
+    ip_address TEXT,
+    user_agent TEXT,
+    viewed_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_share_link_views_link ON share_link_views(share_link_id, viewed_at);
@@ -0,0 +1,33 @@
+-- 信頼できる閲覧者（カウンセラーなど）への読み取り専用の共有
+CREATE TABLE IF NOT EXISTS reader_grants (
+    id UUID PRIMARY KEY,
+    owner_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    reader_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- 分析サマリーの閲覧を許可する
+    analysis_summaries BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+    UNIQUE (owner_id, reader_id),
+    CHECK (owner_id <> reader_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_reader_grants_reader_id ON reader_grants(reader_id);
+
+-- 閲覧を許可したノート
+CREATE TABLE IF NOT EXISTS reader_grant_notebooks (
+    grant_id UUID NOT NULL REFERENCES reader_grants(id) ON DELETE CASCADE,
+    notebook_id UUID NOT NULL REFERENCES notebooks(id) ON DELETE CASCADE,
+    PRIMARY KEY (grant_id, notebook_id)
+);
+
+-- 閲覧者が何を見たかの記録（共有を取り消しても残す）
+CREATE TABLE IF NOT EXISTS reader_access_log (
+    id UUID PRIMARY KEY,
+    owner_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    reader_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    resource TEXT NOT NULL,
+    resource_id UUID,
+    viewed_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_reader_access_log_owner ON reader_access_log(owner_id, viewed_at);
@@ -0,0 +1,19 @@
+-- エンドツーエンド暗号化の鍵情報
+-- データ鍵はクライアントでパスフレーズから導出した鍵で包まれており、サーバーは復号できない
+-- 無効にした後も、暗号化された投稿が残っている間は復号用に鍵を残す
+CREATE TABLE IF NOT EXISTS user_encryption_keys (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    wrapped_key TEXT NOT NULL,
+    wrap_algorithm TEXT NOT NULL,
+    wrap_nonce TEXT NOT NULL,
+    kdf TEXT NOT NULL,
+    kdf_salt TEXT NOT NULL,
+    kdf_params JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- 暗号化された投稿の復号に必要な情報（NULLなら平文）
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS encryption JSONB;
+ALTER TABLE post_revisions ADD COLUMN IF NOT EXISTS encryption JSONB;
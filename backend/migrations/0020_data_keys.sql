@@ -0,0 +1,14 @@
+-- 保存時の暗号化に使うユーザーごとのデータ鍵
+-- データ鍵は設定のマスター鍵で包んで保存し、どのマスター鍵で包んだかを master_key_id に記録する
+CREATE TABLE IF NOT EXISTS user_data_keys (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    wrapped_key TEXT NOT NULL,
+    master_key_id TEXT NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_data_keys_master_key_id ON user_data_keys(master_key_id);
+
+-- リンク先のタイトルも暗号化して保存するため、タイトルでの照合はアプリケーションで行う
+DROP INDEX IF EXISTS idx_post_links_dangling_title;
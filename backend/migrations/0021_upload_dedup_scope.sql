@@ -0,0 +1,9 @@
+-- 同一内容の再利用はユーザーごと・種類（画像/音声）ごとに行う
+-- 公開URLはランダムなファイル名にし、コンテンツハッシュは重複の判定にだけ使う
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS media_kind TEXT;
+UPDATE uploads SET media_kind = split_part(content_type, '/', 1) WHERE media_kind IS NULL;
+ALTER TABLE uploads ALTER COLUMN media_kind SET NOT NULL;
+
+DROP INDEX IF EXISTS idx_uploads_user_content_hash;
+DROP INDEX IF EXISTS idx_uploads_content_hash;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_uploads_user_kind_content_hash ON uploads(user_id, media_kind, content_hash);
@@ -0,0 +1,5 @@
+-- 執筆統計のための本文の単語数・文字数（空白を除く）
+-- 本文は暗号化して保存しているため値はアプリケーションで計算する（既存の投稿は起動時に埋める）
+-- エンドツーエンド暗号化された投稿は数えられないため NULL のまま
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS word_count INTEGER;
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS character_count INTEGER;
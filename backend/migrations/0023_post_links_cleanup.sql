@@ -0,0 +1,2 @@
+-- 完全に削除された投稿へのIDのリンクは、リンク先もタイトルも持たない行として残っていたため削除する
+DELETE FROM post_links WHERE target_post_id IS NULL AND target_title IS NULL;
@@ -0,0 +1,2 @@
+-- エンドツーエンド暗号化された投稿の分析結果は本文の内容を含むため保存しない。保存済みのものを削除する
+DELETE FROM analyses a USING posts p WHERE p.id = a.post_id AND p.encryption IS NOT NULL;
@@ -0,0 +1,29 @@
+-- 保存時の暗号化をしたかどうかを、値の接頭辞ではなく行ごとに記録する
+-- 書き込みと同じ文で設定する。NULL はこの列を追加する前に保存された行で、起動時に暗号化するときに判定する
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS encrypted_at_rest BOOLEAN;
+ALTER TABLE post_revisions ADD COLUMN IF NOT EXISTS encrypted_at_rest BOOLEAN;
+ALTER TABLE post_links ADD COLUMN IF NOT EXISTS encrypted_at_rest BOOLEAN;
+ALTER TABLE analyses ADD COLUMN IF NOT EXISTS encrypted_at_rest BOOLEAN;
+
+ALTER TABLE posts ALTER COLUMN encrypted_at_rest SET DEFAULT FALSE;
+ALTER TABLE post_revisions ALTER COLUMN encrypted_at_rest SET DEFAULT FALSE;
+ALTER TABLE post_links ALTER COLUMN encrypted_at_rest SET DEFAULT FALSE;
+ALTER TABLE analyses ALTER COLUMN encrypted_at_rest SET DEFAULT FALSE;
+
+-- データ鍵のないユーザーの行と、エンドツーエンド暗号化された投稿は暗号化されていない
+UPDATE posts SET encrypted_at_rest = FALSE
+WHERE encryption IS NOT NULL OR user_id NOT IN (SELECT user_id FROM user_data_keys);
+
+UPDATE post_revisions SET encrypted_at_rest = FALSE
+WHERE encryption IS NOT NULL OR user_id NOT IN (SELECT user_id FROM user_data_keys);
+
+UPDATE post_links l SET encrypted_at_rest = FALSE
+FROM posts p
+WHERE p.id = l.source_post_id
+  AND (l.target_title IS NULL OR p.user_id NOT IN (SELECT user_id FROM user_data_keys));
+
+UPDATE analyses SET encrypted_at_rest = FALSE
+WHERE user_id NOT IN (SELECT user_id FROM user_data_keys);
+
+-- 暗号化されていない行を起動時に探すための索引
+CREATE INDEX IF NOT EXISTS idx_posts_not_encrypted_at_rest ON posts(user_id) WHERE encrypted_at_rest IS NOT TRUE AND encryption IS NULL;
@@ -0,0 +1,8 @@
+-- タイトルで書かれたリンクを、タイトルを復号せずにDBで照合するためのハッシュ
+-- 前後の空白を除いて小文字にしたタイトルを、ユーザーのデータ鍵から導いた鍵でHMACにかけた値
+-- （保存時の暗号化が無効な場合は鍵なしのSHA-256）。既存の行は起動時に埋める
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS title_hash TEXT;
+ALTER TABLE post_links ADD COLUMN IF NOT EXISTS target_title_hash TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_posts_title_hash ON posts(user_id, title_hash) WHERE title_hash IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_post_links_dangling_title_hash ON post_links(target_title_hash) WHERE target_post_id IS NULL;
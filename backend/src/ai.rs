@@ -1,3 +1,4 @@
+use base64::Engine;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
@@ -13,8 +14,23 @@ struct GeminiContent {
 }
 
 #[derive(Debug, Serialize)]
-struct GeminiPart {
-    text: String,
+#[serde(untagged)]
+enum GeminiPart {
+    Text { text: String },
+    InlineData { inline_data: InlineData },
+}
+
+#[derive(Debug, Serialize)]
+struct InlineData {
+    mime_type: String,
+    data: String,
+}
+
+/// 分析に含める添付画像
+#[derive(Debug, Clone)]
+pub struct PostImage {
+    pub mime_type: String,
+    pub data: Vec<u8>,
 }
 
 #[derive(Debug, Serialize)]
@@ -54,6 +70,54 @@ struct UsageMetadata {
     total_token_count: i32,
 }
 
+const RATE_LIMIT_MESSAGE: &str = "AI APIのレート制限に達しました。しばらく時間をおいてから再度お試しください。（毎日午前9時にリセットされます）";
+
+/// Gemini にリクエストを送り、最初の候補のテキストと消費トークン数を返す
+///
+/// APIがエラーを返した場合は内側の `Err` にエラー本文を入れて返す（代わりの結果は呼び出し側で作る）。
+async fn generate_content(
+    api_key: &str,
+    parts: Vec<GeminiPart>,
+    generation_config: GenerationConfig,
+) -> Result<Result<(String, i32), String>, anyhow::Error> {
+    let request = GeminiRequest {
+        contents: vec![GeminiContent { parts }],
+        generation_config,
+    };
+
+    let client = reqwest::Client::new();
+    let url = format!(
+        "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent?key={}",
+        api_key
+    );
+
+    let response = client.post(&url).json(&request).send().await?;
+
+    if !response.status().is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        tracing::error!("Gemini API error: {}", error_text);
+        return Ok(Err(error_text));
+    }
+
+    let gemini_response: GeminiResponse = response.json().await?;
+
+    let text = gemini_response.candidates
+        .first()
+        .and_then(|c| c.content.parts.first())
+        .map(|p| p.text.clone())
+        .unwrap_or_default();
+
+    let tokens = gemini_response.usage_metadata
+        .map(|m| m.total_token_count)
+        .unwrap_or(0);
+
+    Ok(Ok((text, tokens)))
+}
+
+fn is_rate_limited(error_text: &str) -> bool {
+    error_text.contains("429") || error_text.contains("RESOURCE_EXHAUSTED")
+}
+
 const ANALYSIS_PROMPT: &str = r#"
 あなたは心理分析の専門家です。以下の日記・ジャーナル投稿を分析し、JSON形式で結果を返してください。
 
@@ -85,63 +149,50 @@ const ANALYSIS_PROMPT: &str = r#"
 JSONのみを返し、他のテキストは含めないでください。
 "#;
 
-pub async fn analyze_post(api_key: &str, title: Option<&str>, content: &str) -> Result<(serde_json::Value, i32), anyhow::Error> {
+const IMAGE_ANALYSIS_PROMPT: &str = r#"
+投稿には{count}枚の画像が添付されています。画像の内容も分析に含め、上記の形式に以下のフィールドを追加してください:
+  "image_descriptions": ["画像1の説明（1文）", "画像2の説明"],
+  "image_topics": ["画像から読み取れるトピック1", "トピック2"]
+"#;
+
+pub async fn analyze_post(api_key: &str, title: Option<&str>, content: &str, images: &[PostImage]) -> Result<(serde_json::Value, i32), anyhow::Error> {
     if api_key.is_empty() {
         return Ok((mock_response("Gemini APIキーが設定されていません"), 0));
     }
 
-    let prompt = ANALYSIS_PROMPT
+    let mut prompt = ANALYSIS_PROMPT
         .replace("{title}", title.unwrap_or("（タイトルなし）"))
         .replace("{content}", content);
-
-    let request = GeminiRequest {
-        contents: vec![GeminiContent {
-            parts: vec![GeminiPart { text: prompt }],
-        }],
-        generation_config: GenerationConfig {
-            temperature: 0.7,
-            max_output_tokens: 1000,
-            response_mime_type: "application/json".to_string(),
-        },
-    };
-
-    let client = reqwest::Client::new();
-    let url = format!(
-        "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent?key={}",
-        api_key
-    );
-
-    let response = client
-        .post(&url)
-        .json(&request)
-        .send()
-        .await?;
-
-    if !response.status().is_success() {
-        let error_text = response.text().await?;
-        tracing::error!("Gemini API error: {}", error_text);
-
-        // レート制限エラーをわかりやすいメッセージに変換
-        let user_message = if error_text.contains("429") || error_text.contains("RESOURCE_EXHAUSTED") {
-            "AI APIのレート制限に達しました。しばらく時間をおいてから再度お試しください。（毎日午前9時にリセットされます）"
-        } else {
-            "AI APIでエラーが発生しました。しばらくしてから再度お試しください。"
-        };
-
-        return Ok((mock_response(user_message), 0));
+    if !images.is_empty() {
+        prompt.push_str(&IMAGE_ANALYSIS_PROMPT.replace("{count}", &images.len().to_string()));
     }
 
-    let gemini_response: GeminiResponse = response.json().await?;
+    let mut parts = vec![GeminiPart::Text { text: prompt }];
+    parts.extend(images.iter().map(|image| GeminiPart::InlineData {
+        inline_data: InlineData {
+            mime_type: image.mime_type.clone(),
+            data: base64::engine::general_purpose::STANDARD.encode(&image.data),
+        },
+    }));
 
-    let text = gemini_response.candidates
-        .first()
-        .and_then(|c| c.content.parts.first())
-        .map(|p| p.text.clone())
-        .unwrap_or_default();
+    let config = GenerationConfig {
+        temperature: 0.7,
+        max_output_tokens: 1000,
+        response_mime_type: "application/json".to_string(),
+    };
 
-    let tokens = gemini_response.usage_metadata
-        .map(|m| m.total_token_count)
-        .unwrap_or(0);
+    let (text, tokens) = match generate_content(api_key, parts, config).await? {
+        Ok(reply) => reply,
+        Err(error_text) => {
+            // レート制限エラーをわかりやすいメッセージに変換
+            let user_message = if is_rate_limited(&error_text) {
+                RATE_LIMIT_MESSAGE
+            } else {
+                "AI APIでエラーが発生しました。しばらくしてから再度お試しください。"
+            };
+            return Ok((mock_response(user_message), 0));
+        }
+    };
 
     let result: serde_json::Value = serde_json::from_str(&text)
         .unwrap_or_else(|_| mock_response("JSONパースエラー"));
@@ -190,50 +241,31 @@ pub async fn generate_user_summary(api_key: &str, analyses_text: &str) -> Result
 JSONのみを返してください。
 "#, analyses_text);
 
-    let request = GeminiRequest {
-        contents: vec![GeminiContent {
-            parts: vec![GeminiPart { text: prompt }],
-        }],
-        generation_config: GenerationConfig {
-            temperature: 0.7,
-            max_output_tokens: 1000,
-            response_mime_type: "application/json".to_string(),
-        },
+    let config = GenerationConfig {
+        temperature: 0.7,
+        max_output_tokens: 1000,
+        response_mime_type: "application/json".to_string(),
     };
 
-    let client = reqwest::Client::new();
-    let url = format!(
-        "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent?key={}",
-        api_key
-    );
-
-    let response = client.post(&url).json(&request).send().await?;
-
-    if !response.status().is_success() {
-        let error_text = response.text().await.unwrap_or_default();
-
-        // レート制限エラーをわかりやすいメッセージに変換
-        let message = if error_text.contains("429") || error_text.contains("RESOURCE_EXHAUSTED") {
-            "AI APIのレート制限に達しました。しばらく時間をおいてから再度お試しください。（毎日午前9時にリセットされます）"
-        } else {
-            "サマリー生成でエラーが発生しました"
-        };
-
-        return Ok(json!({
-            "overall_summary": message,
-            "dominant_emotions": [],
-            "key_interests": [],
-            "personality_overview": "",
-            "recommendations": []
-        }));
-    }
-
-    let gemini_response: GeminiResponse = response.json().await?;
-    let text = gemini_response.candidates
-        .first()
-        .and_then(|c| c.content.parts.first())
-        .map(|p| p.text.clone())
-        .unwrap_or_default();
+    let text = match generate_content(api_key, vec![GeminiPart::Text { text: prompt }], config).await? {
+        Ok((text, _)) => text,
+        Err(error_text) => {
+            // レート制限エラーをわかりやすいメッセージに変換
+            let message = if is_rate_limited(&error_text) {
+                RATE_LIMIT_MESSAGE
+            } else {
+                "サマリー生成でエラーが発生しました"
+            };
+
+            return Ok(json!({
+                "overall_summary": message,
+                "dominant_emotions": [],
+                "key_interests": [],
+                "personality_overview": "",
+                "recommendations": []
+            }));
+        }
+    };
 
     Ok(serde_json::from_str(&text).unwrap_or(json!({
         "overall_summary": "パースエラー",
@@ -243,3 +275,43 @@ JSONのみを返してください。
         "recommendations": []
     })))
 }
+
+/// 最近の分析トピックから日記の問いかけを生成する
+///
+/// APIキーやトピックがない場合、またはAPIエラー時は空を返す（呼び出し側で組み込みの問いかけを使う）。
+pub async fn generate_writing_prompts(api_key: &str, topics: &[String], count: usize) -> Result<Vec<String>, anyhow::Error> {
+    if api_key.is_empty() || topics.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let prompt = format!(r#"
+以下はユーザーが最近の日記で書いたトピックです。
+{}
+
+これらを踏まえて、ユーザーが今日の日記を書くきっかけになる問いかけを{}個、日本語で作成してください。
+責めたり診断したりせず、やさしく具体的な問いかけにしてください。
+
+形式:
+["問いかけ1", "問いかけ2"]
+
+JSONのみを返してください。
+"#, topics.join("、"), count);
+
+    let config = GenerationConfig {
+        temperature: 0.9,
+        max_output_tokens: 500,
+        response_mime_type: "application/json".to_string(),
+    };
+
+    let Ok((text, _)) = generate_content(api_key, vec![GeminiPart::Text { text: prompt }], config).await? else {
+        return Ok(Vec::new());
+    };
+
+    let prompts: Vec<String> = serde_json::from_str(&text).unwrap_or_default();
+    Ok(prompts
+        .into_iter()
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .take(count)
+        .collect())
+}
@@ -1,15 +1,19 @@
 //! 日記分析API
 //!
 //! 投稿をAIで分析し、感情・性格傾向・関心事などを抽出する機能を提供。
-//! - create_analysis: 投稿を分析してDBに保存
+//! - create_analysis: 投稿を分析してDBに保存（設定により添付画像も含める。
+//!   暗号化された投稿はクライアントが復号して送った平文で分析し、結果は保存せずに返す）
+//! - analyze: 分析処理本体（投稿の一括操作からも使用）
 //! - get_analysis: 分析結果を取得
-//! - get_user_summary: ユーザー全体の傾向サマリーを生成（AI呼び出しあり）
+//! - get_user_summary: ユーザー全体（またはノート単位）の傾向サマリーを生成（AI呼び出しあり）
 
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::{header, StatusCode},
     Json,
 };
+use chrono::Utc;
+use serde::Deserialize;
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -17,10 +21,21 @@ use crate::{
     ai,
     auth::verify_token,
     db,
-    models::{Analysis, CreateAnalysisRequest, UserSummary},
+    models::{Analysis, AnalysisPlaintext, CreateAnalysisRequest, Post, PostStatus, UserSummary},
     AppState,
 };
 
+use super::readers::Viewer;
+
+/// 分析に使用するモデル
+const ANALYSIS_MODEL: &str = "gemini-flash-latest";
+
+#[derive(Debug, Deserialize)]
+pub struct SummaryParams {
+    /// 指定した場合はそのノートの投稿のみを対象にする
+    notebook_id: Option<Uuid>,
+}
+
 /// JWTトークンからユーザーIDを抽出する
 fn extract_user_id(headers: &axum::http::HeaderMap, jwt_secret: &str) -> Result<Uuid, (StatusCode, String)> {
     let auth_header = headers
@@ -39,43 +54,181 @@ fn extract_user_id(headers: &axum::http::HeaderMap, jwt_secret: &str) -> Result<
         .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid user id".to_string()))
 }
 
-/// 投稿を分析するエンドポイント
+/// Gemini が画像入力として受け付ける形式のMIMEタイプを拡張子から判定する
 ///
-/// 指定された投稿をGemini AIで分析し、結果をDBに保存する。
-/// 分析内容: 感情スコア、トピック、性格傾向、関心事、サマリー
-pub async fn create_analysis(
-    State(state): State<Arc<AppState>>,
-    headers: axum::http::HeaderMap,
-    Json(req): Json<CreateAnalysisRequest>,
-) -> Result<Json<Analysis>, (StatusCode, String)> {
-    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+/// 拡張子はアップロード時に検証したContent-Typeから付けたもの（`uploads::store_file`）。
+fn image_mime_type(filename: &str) -> Option<&'static str> {
+    let ext = filename.rsplit('.').next()?.to_ascii_lowercase();
+    match ext.as_str() {
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "png" => Some("image/png"),
+        "webp" => Some("image/webp"),
+        "heic" => Some("image/heic"),
+        "heif" => Some("image/heif"),
+        _ => None,
+    }
+}
 
-    // 投稿を取得
-    let post = db::get_post_by_id(&state.db, &req.post_id, &user_id)
+/// 投稿に添付された画像を読み込む
+///
+/// 投稿者がアップロードした画像のみを対象とし、合計サイズが `budget` を超える画像は
+/// 読み込まずに飛ばす（後ろの小さい画像は含める）。
+async fn load_post_images(state: &AppState, post: &Post, budget: i64) -> Result<Vec<ai::PostImage>, (StatusCode, String)> {
+    let urls: Vec<String> = serde_json::from_value(post.image_urls.clone()).unwrap_or_default();
+    let candidates: Vec<(String, &'static str)> = urls
+        .iter()
+        .filter_map(|url| url.strip_prefix("/uploads/"))
+        .filter(|filename| !filename.contains('/') && !filename.contains(".."))
+        .filter_map(|filename| Some((filename.to_string(), image_mime_type(filename)?)))
+        .collect();
+    if candidates.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let filenames: Vec<String> = candidates.iter().map(|(filename, _)| filename.clone()).collect();
+    let owned = db::get_owned_upload_filenames(&state.db, &post.user_id, &filenames)
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-        .ok_or((StatusCode::NOT_FOUND, "Post not found".to_string()))?;
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let mut images = Vec::new();
+    let mut total = 0i64;
+    for (filename, mime_type) in candidates {
+        if !owned.contains(&filename) {
+            continue;
+        }
+        let path = format!("uploads/{}", filename);
+
+        let size = match tokio::fs::metadata(&path).await {
+            Ok(metadata) => metadata.len() as i64,
+            Err(e) => {
+                tracing::warn!("Failed to read image {}: {}", filename, e);
+                continue;
+            }
+        };
+        if total + size > budget {
+            continue;
+        }
+
+        let data = match tokio::fs::read(&path).await {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::warn!("Failed to read image {}: {}", filename, e);
+                continue;
+            }
+        };
+        total += data.len() as i64;
+        images.push(ai::PostImage {
+            mime_type: mime_type.to_string(),
+            data,
+        });
+    }
+    Ok(images)
+}
+
+/// 投稿を分析してDBに保存する
+///
+/// ユーザーが画像分析を有効にしている場合は添付画像も送信し、画像の説明とトピックを追加する。
+/// 下書きは分析できない。
+/// エンドツーエンド暗号化された投稿は `plaintext`（クライアントで復号した内容）が必須で、
+/// 平文も分析結果も本文の内容を含むため保存せず、分析結果はレスポンスでのみ返す
+/// （保存していないため、返したIDで後から取得することはできない）。
+pub async fn analyze(
+    state: &AppState,
+    post: &Post,
+    user_id: &Uuid,
+    plaintext: Option<&AnalysisPlaintext>,
+) -> Result<Analysis, (StatusCode, String)> {
+    if post.status == PostStatus::Draft.as_str() {
+        return Err((StatusCode::BAD_REQUEST, "Drafts cannot be analyzed".to_string()));
+    }
+
+    let (title, content) = match (post.encryption.is_some(), plaintext) {
+        (false, None) => (post.title.as_deref(), post.content.as_str()),
+        (true, Some(plaintext)) => (plaintext.title.as_deref(), plaintext.content.as_str()),
+        (true, None) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Encrypted posts can only be analyzed with plaintext from the client".to_string(),
+            ));
+        }
+        (false, Some(_)) => {
+            return Err((StatusCode::BAD_REQUEST, "plaintext is only accepted for encrypted posts".to_string()));
+        }
+    };
+
+    // 分析対象のリビジョンを記録する
+    let revision_id = db::get_latest_revision_id(&state.db, &post.id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // 画像分析がオプトインされていれば添付画像を読み込む（分析に使うモデルは画像入力に対応している）
+    let preferences = db::get_user_preferences(&state.db, user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let images = match preferences {
+        Some(p) if p.analyze_images => {
+            load_post_images(state, post, state.analysis_image_budget_bytes).await?
+        }
+        _ => Vec::new(),
+    };
 
     // Gemini AIで投稿を分析
     let (result, tokens) = ai::analyze_post(
         &state.gemini_api_key,
-        post.title.as_deref(),
-        &post.content,
+        title,
+        content,
+        &images,
     )
     .await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    if post.encryption.is_some() {
+        return Ok(Analysis {
+            id: Uuid::new_v4(),
+            post_id: post.id,
+            user_id: *user_id,
+            analysis_type: "full".to_string(),
+            result,
+            encrypted_at_rest: Some(false),
+            tokens_used: Some(tokens),
+            model_version: Some(ANALYSIS_MODEL.to_string()),
+            created_at: Some(Utc::now()),
+            revision_id,
+        });
+    }
+
     // 分析結果をDBに保存
-    let analysis = db::create_analysis(
+    db::create_analysis(
         &state.db,
-        &req.post_id,
-        &user_id,
+        &post.id,
+        user_id,
         result,
         tokens,
-        "gemini-flash-latest",
+        ANALYSIS_MODEL,
+        revision_id.as_ref(),
     )
     .await
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// 投稿を分析するエンドポイント
+///
+/// 指定された投稿をGemini AIで分析し、結果をDBに保存する。
+/// 分析内容: 感情スコア、トピック、性格傾向、関心事、サマリー
+pub async fn create_analysis(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Json(req): Json<CreateAnalysisRequest>,
+) -> Result<Json<Analysis>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    // 投稿を取得
+    let post = db::get_post_by_id(&state.db, &req.post_id, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Post not found".to_string()))?;
+
+    let analysis = analyze(&state, &post, &user_id, req.plaintext.as_ref()).await?;
 
     Ok(Json(analysis))
 }
@@ -83,18 +236,31 @@ pub async fn create_analysis(
 /// 投稿の分析結果を取得するエンドポイント
 ///
 /// DBから保存済みの分析結果を取得する（AI呼び出しなし）
+/// 閲覧者は共有されたノートの投稿の分析結果のみ取得できる。
 pub async fn get_analysis(
     State(state): State<Arc<AppState>>,
     headers: axum::http::HeaderMap,
     Path(post_id): Path<Uuid>,
 ) -> Result<Json<Analysis>, (StatusCode, String)> {
     let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    let viewer = super::readers::resolve_viewer(&state, &headers, user_id).await?;
+    let owner_id = viewer.owner_id();
 
-    let analysis = db::get_analysis_by_post(&state.db, &post_id, &user_id)
+    if let Viewer::Reader(_) = viewer {
+        db::get_post_by_id(&state.db, &post_id, &owner_id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .filter(|post| viewer.can_read_post(post))
+            .ok_or((StatusCode::NOT_FOUND, "Analysis not found".to_string()))?;
+    }
+
+    let analysis = db::get_analysis_by_post(&state.db, &post_id, &owner_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
         .ok_or((StatusCode::NOT_FOUND, "Analysis not found".to_string()))?;
 
+    viewer.log_access(&state, "analysis", Some(&post_id)).await?;
+
     Ok(Json(analysis))
 }
 
@@ -103,19 +269,25 @@ pub async fn get_analysis(
 /// 直近10件の分析結果を基に、AIで全体的な傾向サマリーを生成する。
 /// 注意: このエンドポイントはAI APIを呼び出すため、Analysisページでのみ使用。
 /// ダッシュボードでは呼び出さない（レート制限対策）
+/// 閲覧者は分析サマリーが共有されている場合のみ、共有されたノートの範囲で取得できる。
 pub async fn get_user_summary(
     State(state): State<Arc<AppState>>,
     headers: axum::http::HeaderMap,
+    Query(params): Query<SummaryParams>,
 ) -> Result<Json<UserSummary>, (StatusCode, String)> {
     let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    let viewer = super::readers::resolve_viewer(&state, &headers, user_id).await?;
+    let notebooks = viewer.summary_notebook_scope(params.notebook_id)?;
+    let notebook = notebooks.as_deref();
+    let user_id = viewer.owner_id();
 
     // 直近10件の分析結果を取得
-    let analyses = db::get_user_analyses(&state.db, &user_id, 10)
+    let analyses = db::get_user_analyses(&state.db, &user_id, 10, notebook)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     // 分析総数を取得
-    let total = db::count_user_analyses(&state.db, &user_id)
+    let total = db::count_user_analyses(&state.db, &user_id, notebook)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
@@ -132,6 +304,8 @@ pub async fn get_user_summary(
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    viewer.log_access(&state, "summary", None).await?;
+
     Ok(Json(UserSummary {
         user_id,
         total_posts_analyzed: total,
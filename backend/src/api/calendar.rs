@@ -0,0 +1,94 @@
+//! カレンダーAPI
+//!
+//! ダッシュボードのヒートマップ用に、年または月単位で日ごとの活動量を返す。
+//! 日付はユーザーのタイムゾーンで区切る。
+//! - get_calendar: 日ごとの投稿数・主な気分・喜び/悲しみの平均・TODO達成率（閲覧者には共有されたノートの投稿のみでTODOを含めない）
+
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    Json,
+};
+use chrono::{Datelike, NaiveDate};
+use serde::Deserialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use super::readers::Viewer;
+use crate::{auth::verify_token, db, models::CalendarResponse, timezone, AppState};
+
+#[derive(Debug, Deserialize)]
+pub struct CalendarParams {
+    /// 省略時は今年
+    year: Option<i32>,
+    /// 指定した場合はその月のみ
+    month: Option<u32>,
+    /// 指定した場合はそのノートの投稿のみ
+    notebook_id: Option<Uuid>,
+}
+
+/// JWTトークンからユーザーIDを抽出する
+fn extract_user_id(headers: &axum::http::HeaderMap, jwt_secret: &str) -> Result<Uuid, (StatusCode, String)> {
+    let auth_header = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, "Missing authorization".to_string()))?;
+
+    let token = auth_header
+        .strip_prefix("Bearer ")
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    let claims = verify_token(token, jwt_secret)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    Uuid::parse_str(&claims.sub)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid user id".to_string()))
+}
+
+/// 期間 `[start, end)` を求める
+fn period(year: i32, month: Option<u32>) -> Option<(NaiveDate, NaiveDate)> {
+    match month {
+        Some(month) => {
+            let start = NaiveDate::from_ymd_opt(year, month, 1)?;
+            let end = if month == 12 {
+                NaiveDate::from_ymd_opt(year + 1, 1, 1)?
+            } else {
+                NaiveDate::from_ymd_opt(year, month + 1, 1)?
+            };
+            Some((start, end))
+        }
+        None => Some((NaiveDate::from_ymd_opt(year, 1, 1)?, NaiveDate::from_ymd_opt(year + 1, 1, 1)?)),
+    }
+}
+
+pub async fn get_calendar(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Query(params): Query<CalendarParams>,
+) -> Result<Json<CalendarResponse>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    let viewer = super::readers::resolve_viewer(&state, &headers, user_id).await?;
+    let owner_id = viewer.owner_id();
+    let notebooks = viewer.notebook_scope(params.notebook_id)?;
+    // TODOはノートに属さないため閲覧者には見せない
+    let include_todos = matches!(viewer, Viewer::Owner(_));
+
+    let time_zone = super::settings::user_time_zone(&state, &owner_id).await?;
+    let year = params.year.unwrap_or_else(|| timezone::today(time_zone).year());
+
+    let (start, end) = period(year, params.month)
+        .ok_or((StatusCode::BAD_REQUEST, "Invalid year or month".to_string()))?;
+
+    let days = db::get_calendar_days(&state.db, &owner_id, start, end, time_zone.name(), notebooks.as_deref(), include_todos)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    viewer.log_access(&state, "calendar", None).await?;
+
+    Ok(Json(CalendarResponse {
+        year,
+        month: params.month,
+        time_zone: time_zone.name().to_string(),
+        days,
+    }))
+}
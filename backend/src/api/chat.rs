@@ -1,6 +1,8 @@
 //! AIチャット機能
 //!
 //! ユーザーとAIの会話を通じて日記作成を支援する機能を提供します。
+//! 会話はリクエストごとにクライアントから受け取り、サーバーには保存しません
+//! （保存時の暗号化の対象外なのはこのため。保存する場合は `db` で暗号化すること）。
 //! - chat: AIとの会話エンドポイント
 //! - summarize: 会話内容から日記を生成するエンドポイント
 
@@ -0,0 +1,223 @@
+//! エンドツーエンド暗号化API
+//!
+//! 投稿の本文とタイトルをクライアントで暗号化し、サーバーには暗号文と鍵の包み方の情報だけを保存する。
+//! データ鍵はパスフレーズから導出した鍵でクライアントが包んで登録するため、サーバーは復号できない。
+//! 有効にしている間は平文を必要とする機能（検索、サーバーでの表示用HTML変換、共有リンク、
+//! 自動保存、テンプレート、音声投稿）を使えず、AI分析はクライアントが送った平文でのみ行う。
+//! - get_encryption: 暗号化の設定と包んだ鍵を取得
+//! - set_encryption: 暗号化を有効にする（パスフレーズ変更時は鍵を包み直して置き換える）
+//! - disable_encryption: 暗号化を無効にする（暗号化された投稿が残っている間は復号用に鍵を残す）
+//! - check_post_write: 投稿の書き込みが暗号化の設定に合っているか確認
+
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    Json,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    auth::verify_token,
+    db,
+    models::{EncryptionKey, EncryptionSettings, PostEncryption, SetEncryptionKeyRequest},
+    AppState,
+};
+
+/// 本文の暗号化と鍵の包み方に使える方式
+const ALGORITHMS: &[&str] = &["aes-256-gcm", "xchacha20-poly1305"];
+
+/// パスフレーズからの鍵導出に使える方式
+const KDFS: &[&str] = &["argon2id", "pbkdf2-sha256"];
+
+/// JWTトークンからユーザーIDを抽出する
+fn extract_user_id(headers: &axum::http::HeaderMap, jwt_secret: &str) -> Result<Uuid, (StatusCode, String)> {
+    let auth_header = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, "Missing authorization".to_string()))?;
+
+    let token = auth_header
+        .strip_prefix("Bearer ")
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    let claims = verify_token(token, jwt_secret)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    Uuid::parse_str(&claims.sub)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid user id".to_string()))
+}
+
+/// 空でないbase64か確認する
+fn validate_base64(value: &str, field: &str) -> Result<(), (StatusCode, String)> {
+    match STANDARD.decode(value) {
+        Ok(bytes) if !bytes.is_empty() => Ok(()),
+        _ => Err((StatusCode::BAD_REQUEST, format!("{} must be non-empty base64", field))),
+    }
+}
+
+fn validate_algorithm(algorithm: &str, field: &str) -> Result<(), (StatusCode, String)> {
+    if !ALGORITHMS.contains(&algorithm) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("{} must be one of: {}", field, ALGORITHMS.join(", ")),
+        ));
+    }
+    Ok(())
+}
+
+fn settings(key: Option<EncryptionKey>) -> EncryptionSettings {
+    EncryptionSettings {
+        enabled: key.as_ref().is_some_and(|k| k.enabled),
+        key,
+    }
+}
+
+async fn get_key(state: &AppState, user_id: &Uuid) -> Result<Option<EncryptionKey>, (StatusCode, String)> {
+    db::get_encryption_key(&state.db, user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// ユーザーがエンドツーエンド暗号化を有効にしているか
+pub async fn is_enabled(state: &AppState, user_id: &Uuid) -> Result<bool, (StatusCode, String)> {
+    Ok(get_key(state, user_id).await?.is_some_and(|k| k.enabled))
+}
+
+/// 平文を必要とする機能を、暗号化を有効にしているユーザーには使わせない
+pub async fn ensure_disabled(state: &AppState, user_id: &Uuid, feature: &str) -> Result<(), (StatusCode, String)> {
+    if is_enabled(state, user_id).await? {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("{} is unavailable with end-to-end encryption", feature),
+        ));
+    }
+    Ok(())
+}
+
+/// 投稿の書き込みが暗号化の設定に合っているか確認し、保存する復号情報を返す
+///
+/// 本文もタイトルも書き込まない場合は `None`（暗号化の状態を変えない）。
+/// 暗号化が有効な場合、本文かタイトルを書き込むときは `encryption` と本文が必須で、
+/// 本文とタイトルはbase64の暗号文でなければならない。
+/// 無効な場合は `encryption` を受け付けず、本文を書き込むと平文の投稿になる。
+pub async fn check_post_write(
+    state: &AppState,
+    user_id: &Uuid,
+    title: Option<&str>,
+    content: Option<&str>,
+    encryption: Option<&PostEncryption>,
+) -> Result<Option<Option<serde_json::Value>>, (StatusCode, String)> {
+    let key = get_key(state, user_id).await?;
+
+    if !key.as_ref().is_some_and(|k| k.enabled) {
+        if encryption.is_some() {
+            return Err((StatusCode::BAD_REQUEST, "End-to-end encryption is not enabled".to_string()));
+        }
+        if content.is_some() {
+            return Ok(Some(None));
+        }
+        // 暗号化された投稿のタイトルだけを平文にすることはできない
+        if title.is_some() && key.is_some() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "content is required when changing the title while encrypted posts remain".to_string(),
+            ));
+        }
+        return Ok(None);
+    }
+
+    let Some(encryption) = encryption else {
+        if title.is_none() && content.is_none() {
+            return Ok(None);
+        }
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Content must be encrypted while end-to-end encryption is enabled".to_string(),
+        ));
+    };
+    let content = content.ok_or((
+        StatusCode::BAD_REQUEST,
+        "content is required with encryption".to_string(),
+    ))?;
+
+    validate_algorithm(&encryption.algorithm, "algorithm")?;
+    validate_base64(&encryption.content_nonce, "content_nonce")?;
+    validate_base64(content, "content")?;
+    match (title, encryption.title_nonce.as_deref()) {
+        (Some(title), Some(title_nonce)) => {
+            validate_base64(title_nonce, "title_nonce")?;
+            validate_base64(title, "title")?;
+        }
+        (None, None) => {}
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "title and title_nonce must be given together".to_string(),
+            ));
+        }
+    }
+
+    serde_json::to_value(encryption)
+        .map(|value| Some(Some(value)))
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+pub async fn get_encryption(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<EncryptionSettings>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    Ok(Json(settings(get_key(&state, &user_id).await?)))
+}
+
+/// 包んだデータ鍵を登録して暗号化を有効にする
+///
+/// 既存の投稿は平文のまま残るため、クライアントが暗号化して保存し直す。
+/// 無効にした後に再び有効にする場合は、残っている暗号化された投稿と同じデータ鍵を包んで送る。
+pub async fn set_encryption(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Json(mut req): Json<SetEncryptionKeyRequest>,
+) -> Result<Json<EncryptionSettings>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    validate_base64(&req.wrapped_key, "wrapped_key")?;
+    validate_algorithm(&req.wrap_algorithm, "wrap_algorithm")?;
+    validate_base64(&req.wrap_nonce, "wrap_nonce")?;
+    validate_base64(&req.kdf_salt, "kdf_salt")?;
+    if !KDFS.contains(&req.kdf.as_str()) {
+        return Err((StatusCode::BAD_REQUEST, format!("kdf must be one of: {}", KDFS.join(", "))));
+    }
+    match req.kdf_params {
+        serde_json::Value::Null => req.kdf_params = serde_json::json!({}),
+        serde_json::Value::Object(_) => {}
+        _ => return Err((StatusCode::BAD_REQUEST, "kdf_params must be an object".to_string())),
+    }
+
+    let key = db::set_encryption_key(&state.db, &user_id, &req)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(settings(Some(key))))
+}
+
+/// 暗号化を無効にする
+///
+/// 以降は平文で保存する。暗号化された投稿（ゴミ箱を含む）が残っている間は、
+/// クライアントが復号して平文で保存し直せるよう鍵を残す。すべて平文に戻した後に
+/// もう一度呼び出すと鍵が削除される。
+pub async fn disable_encryption(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<EncryptionSettings>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    let key = db::disable_encryption(&state.db, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(settings(key)))
+}
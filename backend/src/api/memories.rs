@@ -0,0 +1,73 @@
+//! 思い出API
+//!
+//! 過去の同じ日付に書いた投稿を分析結果とともに返し、ダッシュボードで振り返れるようにする。
+//! - get_memories: 前年以前の同じ日（`week=true` で前後3日）の投稿（閲覧者には共有されたノートの投稿のみ）
+
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    Json,
+};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{auth::verify_token, db, models::MemoriesResponse, timezone, AppState};
+
+/// `week=true` のときに同じ日とみなす前後の日数
+const WEEK_WINDOW_DAYS: i32 = 3;
+
+#[derive(Debug, Deserialize)]
+pub struct MemoriesParams {
+    /// 省略時はユーザーのタイムゾーンでの今日
+    date: Option<NaiveDate>,
+    #[serde(default)]
+    week: bool,
+}
+
+/// JWTトークンからユーザーIDを抽出する
+fn extract_user_id(headers: &axum::http::HeaderMap, jwt_secret: &str) -> Result<Uuid, (StatusCode, String)> {
+    let auth_header = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, "Missing authorization".to_string()))?;
+
+    let token = auth_header
+        .strip_prefix("Bearer ")
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    let claims = verify_token(token, jwt_secret)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    Uuid::parse_str(&claims.sub)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid user id".to_string()))
+}
+
+pub async fn get_memories(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Query(params): Query<MemoriesParams>,
+) -> Result<Json<MemoriesResponse>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    let viewer = super::readers::resolve_viewer(&state, &headers, user_id).await?;
+    let user_id = viewer.owner_id();
+    let notebooks = viewer.notebook_scope(None)?;
+
+    let time_zone = super::settings::user_time_zone(&state, &user_id).await?;
+    let date = params.date.unwrap_or_else(|| timezone::today(time_zone));
+    let window_days = if params.week { WEEK_WINDOW_DAYS } else { 0 };
+
+    let memories = db::get_memories(&state.db, &user_id, date, window_days, time_zone.name(), notebooks.as_deref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let post_ids: Vec<Uuid> = memories.iter().map(|memory| memory.post.id).collect();
+    viewer.log_access_many(&state, "memories", &post_ids).await?;
+
+    Ok(Json(MemoriesResponse {
+        date,
+        window_days,
+        memories,
+    }))
+}
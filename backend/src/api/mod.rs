@@ -5,3 +5,16 @@ pub mod uploads;
 pub mod todos;
 pub mod settings;
 pub mod chat;
+pub mod tags;
+pub mod moods;
+pub mod revisions;
+pub mod trash;
+pub mod calendar;
+pub mod stats;
+pub mod memories;
+pub mod notebooks;
+pub mod templates;
+pub mod prompts;
+pub mod shares;
+pub mod readers;
+pub mod encryption;
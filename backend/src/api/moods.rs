@@ -0,0 +1,179 @@
+//! 気分API
+//!
+//! 気分の段階（最高〜最悪）とユーザーごとの表示ラベル、気分の集計を提供。
+//! - list_moods: 気分の一覧（ユーザーのラベル適用済み）
+//! - update_mood_label / reset_mood_label: 表示ラベルの変更・リセット
+//! - get_mood_stats: 期間内の気分ごとの投稿数（ノート単位も可）
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    Json,
+};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    auth::verify_token,
+    db,
+    models::{Mood, MoodOption, MoodStats, UpdateMoodLabelRequest, MOOD_INTENSITY_RANGE},
+    AppState,
+};
+
+/// 表示ラベルの最大文字数
+const MAX_LABEL_LENGTH: usize = 20;
+
+#[derive(Debug, Deserialize)]
+pub struct StatsParams {
+    date_from: Option<NaiveDate>,
+    date_to: Option<NaiveDate>,
+    notebook_id: Option<Uuid>,
+}
+
+/// JWTトークンからユーザーIDを抽出する
+fn extract_user_id(headers: &axum::http::HeaderMap, jwt_secret: &str) -> Result<Uuid, (StatusCode, String)> {
+    let auth_header = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, "Missing authorization".to_string()))?;
+
+    let token = auth_header
+        .strip_prefix("Bearer ")
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    let claims = verify_token(token, jwt_secret)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    Uuid::parse_str(&claims.sub)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid user id".to_string()))
+}
+
+/// 気分の強さが範囲内か検証する
+pub fn validate_intensity(intensity: Option<i16>) -> Result<(), (StatusCode, String)> {
+    match intensity {
+        Some(value) if !MOOD_INTENSITY_RANGE.contains(&value) => Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "mood_intensity must be between {} and {}",
+                MOOD_INTENSITY_RANGE.start(),
+                MOOD_INTENSITY_RANGE.end()
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// 気分の一覧を返す（ユーザーが設定したラベルがあればそれを使う）
+pub async fn list_moods(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Vec<MoodOption>>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    let viewer = super::readers::resolve_viewer(&state, &headers, user_id).await?;
+
+    let labels = db::get_mood_labels(&state.db, &viewer.owner_id())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let moods = Mood::ALL
+        .iter()
+        .map(|&mood| MoodOption {
+            mood,
+            label: labels
+                .iter()
+                .find(|(m, _)| m == mood.as_str())
+                .map(|(_, label)| label.clone())
+                .unwrap_or_else(|| mood.default_label().to_string()),
+            score: mood.score(),
+        })
+        .collect();
+
+    Ok(Json(moods))
+}
+
+pub async fn update_mood_label(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(mood): Path<Mood>,
+    Json(req): Json<UpdateMoodLabelRequest>,
+) -> Result<Json<MoodOption>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    let label = req.label.trim();
+    if label.is_empty() || label.chars().count() > MAX_LABEL_LENGTH {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Label must be between 1 and {} characters", MAX_LABEL_LENGTH),
+        ));
+    }
+
+    db::set_mood_label(&state.db, &user_id, mood.as_str(), label)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(MoodOption {
+        mood,
+        label: label.to_string(),
+        score: mood.score(),
+    }))
+}
+
+/// 表示ラベルをデフォルトに戻す
+pub async fn reset_mood_label(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(mood): Path<Mood>,
+) -> Result<Json<MoodOption>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    db::delete_mood_label(&state.db, &user_id, mood.as_str())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(MoodOption {
+        mood,
+        label: mood.default_label().to_string(),
+        score: mood.score(),
+    }))
+}
+
+/// 期間内の気分ごとの投稿数を集計する
+///
+/// 平均スコアは段階が定義されている気分のみを対象とする
+/// （自由入力だった頃の値は件数にのみ含まれる）。
+pub async fn get_mood_stats(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Query(params): Query<StatsParams>,
+) -> Result<Json<MoodStats>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    let viewer = super::readers::resolve_viewer(&state, &headers, user_id).await?;
+    let owner_id = viewer.owner_id();
+    let notebooks = viewer.notebook_scope(params.notebook_id)?;
+
+    let time_zone = super::settings::user_time_zone(&state, &owner_id).await?;
+
+    let moods = db::get_mood_counts(&state.db, &owner_id, params.date_from, params.date_to, time_zone.name(), notebooks.as_deref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    viewer.log_access(&state, "moods", None).await?;
+
+    let total = moods.iter().map(|m| m.count).sum();
+
+    let (score_sum, score_count) = moods.iter().fold((0i64, 0i64), |(sum, count), m| {
+        match Mood::ALL.iter().find(|mood| mood.as_str() == m.mood) {
+            Some(mood) => (sum + mood.score() as i64 * m.count, count + m.count),
+            None => (sum, count),
+        }
+    });
+    let average_score = (score_count > 0).then(|| score_sum as f64 / score_count as f64);
+
+    Ok(Json(MoodStats {
+        moods,
+        total,
+        average_score,
+    }))
+}
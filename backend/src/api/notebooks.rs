@@ -0,0 +1,135 @@
+//! ノートAPI
+//!
+//! 投稿を「仕事」「カウンセリング」「旅行」などのノートにまとめる。
+//! 投稿一覧・気分の集計・分析サマリーは `notebook_id` でノート単位に絞り込める。
+//! - list_notebooks: ノート一覧（投稿数付き）
+//! - create_notebook / update_notebook / delete_notebook: ノートのCRUD
+
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    Json,
+};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    auth::verify_token,
+    db,
+    models::{Notebook, NotebookRequest, NotebookWithCount},
+    AppState,
+};
+
+/// ノート名の最大文字数
+const MAX_NAME_LENGTH: usize = 50;
+
+/// JWTトークンからユーザーIDを抽出する
+fn extract_user_id(headers: &axum::http::HeaderMap, jwt_secret: &str) -> Result<Uuid, (StatusCode, String)> {
+    let auth_header = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, "Missing authorization".to_string()))?;
+
+    let token = auth_header
+        .strip_prefix("Bearer ")
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    let claims = verify_token(token, jwt_secret)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    Uuid::parse_str(&claims.sub)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid user id".to_string()))
+}
+
+fn normalize_name(name: &str) -> Result<&str, (StatusCode, String)> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Notebook name is required".to_string()));
+    }
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Notebook name must be at most {} characters", MAX_NAME_LENGTH),
+        ));
+    }
+    Ok(name)
+}
+
+/// 投稿に指定されたノートがユーザーのものか確認する
+pub async fn ensure_notebook(state: &AppState, id: &Uuid, user_id: &Uuid) -> Result<(), (StatusCode, String)> {
+    db::get_notebook(&state.db, id, user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::BAD_REQUEST, "Notebook not found".to_string()))?;
+    Ok(())
+}
+
+pub async fn list_notebooks(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Vec<NotebookWithCount>>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    let notebooks = db::get_notebooks_with_counts(&state.db, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(notebooks))
+}
+
+pub async fn create_notebook(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Json(req): Json<NotebookRequest>,
+) -> Result<Json<Notebook>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    let name = normalize_name(&req.name)?;
+
+    let notebook = db::create_notebook(&state.db, &user_id, name, req.description.as_deref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::CONFLICT, "Notebook already exists".to_string()))?;
+
+    Ok(Json(notebook))
+}
+
+pub async fn update_notebook(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(req): Json<NotebookRequest>,
+) -> Result<Json<Notebook>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    let name = normalize_name(&req.name)?;
+
+    let notebook = db::update_notebook(&state.db, &id, &user_id, name, req.description.as_deref())
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db_err) if db_err.is_unique_violation() => {
+                (StatusCode::CONFLICT, "Notebook already exists".to_string())
+            }
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?
+        .ok_or((StatusCode::NOT_FOUND, "Notebook not found".to_string()))?;
+
+    Ok(Json(notebook))
+}
+
+/// ノートを削除する（投稿は削除されずノートから外れる）
+pub async fn delete_notebook(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    let deleted = db::delete_notebook(&state.db, &id, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if !deleted {
+        return Err((StatusCode::NOT_FOUND, "Notebook not found".to_string()));
+    }
+
+    Ok(Json(serde_json::json!({"message": "Notebook deleted"})))
+}
@@ -1,19 +1,39 @@
 use axum::{
     extract::{Path, Query, State},
-    http::{header, StatusCode},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
+use std::collections::HashSet;
 use std::sync::Arc;
 use uuid::Uuid;
 
 use crate::{
     auth::verify_token,
     db,
-    models::{CreatePostRequest, Post, PostListResponse, UpdatePostRequest},
+    models::{
+        AutosaveRequest, Backlink, BulkAction, BulkItemResult, BulkPostRequest, BulkPostResponse, ContentFormat,
+        CreatePostRequest, CreateVoicePostRequest, Mood, Post, PostListResponse, PostStatus, RenderedPost,
+        UpdatePostRequest, Upload,
+    },
+    render, timezone,
     AppState,
 };
 
+use super::readers::Viewer;
+
+/// 1ページあたりの投稿数の上限
+const MAX_PER_PAGE: i32 = 100;
+
+/// 一括操作の対象にできる投稿数の上限
+const MAX_BULK_POSTS: usize = 500;
+
+/// 一括の再分析の上限（投稿ごとにAIを順に呼び出すため、他の操作より少ない）
+const MAX_BULK_ANALYSES: usize = 20;
+
 #[derive(Debug, Deserialize)]
 pub struct ListParams {
     page: Option<i32>,
@@ -21,6 +41,12 @@ pub struct ListParams {
     search: Option<String>,
     date_from: Option<String>,
     date_to: Option<String>,
+    tag: Option<String>,
+    mood: Option<Mood>,
+    cursor: Option<String>,
+    /// `draft` で下書きを一覧する
+    status: Option<PostStatus>,
+    notebook_id: Option<Uuid>,
 }
 
 fn extract_user_id(headers: &axum::http::HeaderMap, jwt_secret: &str) -> Result<Uuid, (StatusCode, String)> {
@@ -40,36 +66,176 @@ fn extract_user_id(headers: &axum::http::HeaderMap, jwt_secret: &str) -> Result<
         .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid user id".to_string()))
 }
 
+/// 投稿の現在のバージョンを表す強いETag
+pub fn etag(post: &Post) -> HeaderValue {
+    HeaderValue::from_str(&format!("\"{}\"", post.version)).expect("valid etag")
+}
+
+/// `If-Match` を更新前に期待するバージョンとして解釈する（`*` はどのバージョンにも一致する）
+pub fn parse_if_match(headers: &HeaderMap) -> Result<Option<i32>, (StatusCode, String)> {
+    let value = headers
+        .get(header::IF_MATCH)
+        .and_then(|h| h.to_str().ok())
+        .ok_or((StatusCode::PRECONDITION_REQUIRED, "If-Match header is required".to_string()))?
+        .trim();
+
+    if value == "*" {
+        return Ok(None);
+    }
+
+    value
+        .trim_start_matches("W/")
+        .trim_matches('"')
+        .parse()
+        .map(Some)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid If-Match header".to_string()))
+}
+
+/// `post` の並び順上の位置を不透明なカーソル文字列にする
+fn encode_cursor(post: &Post) -> String {
+    let raw = format!("{}|{}", post.entry_date.timestamp_micros(), post.id);
+    URL_SAFE_NO_PAD.encode(raw)
+}
+
+fn decode_cursor(cursor: &str) -> Result<(DateTime<Utc>, Uuid), (StatusCode, String)> {
+    let invalid = || (StatusCode::BAD_REQUEST, "Invalid cursor".to_string());
+
+    let raw = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+    let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+    let (micros, id) = raw.split_once('|').ok_or_else(invalid)?;
+
+    let entry_date = micros
+        .parse()
+        .ok()
+        .and_then(DateTime::from_timestamp_micros)
+        .ok_or_else(invalid)?;
+    let id = Uuid::parse_str(id).map_err(|_| invalid())?;
+
+    Ok((entry_date, id))
+}
+
+/// `date_from`/`date_to` の絞り込みを解釈する（日付のみやタイムゾーンなしの値はユーザーのタイムゾーンで読む）
+async fn parse_date_bounds(
+    state: &AppState,
+    user_id: &Uuid,
+    date_from: Option<&str>,
+    date_to: Option<&str>,
+) -> Result<(Option<DateTime<Utc>>, Option<DateTime<Utc>>), (StatusCode, String)> {
+    if date_from.is_none() && date_to.is_none() {
+        return Ok((None, None));
+    }
+
+    let time_zone = super::settings::user_time_zone(state, user_id).await?;
+    let date_from = date_from
+        .map(|value| timezone::parse_bound(value, time_zone, false))
+        .map(|bound| bound.ok_or((StatusCode::BAD_REQUEST, "Invalid date_from".to_string())))
+        .transpose()?;
+    let date_to = date_to
+        .map(|value| timezone::parse_bound(value, time_zone, true))
+        .map(|bound| bound.ok_or((StatusCode::BAD_REQUEST, "Invalid date_to".to_string())))
+        .transpose()?;
+
+    Ok((date_from, date_to))
+}
+
+/// 投稿を記録日の新しい順に返す
+///
+/// `cursor` がなければ `page`/`per_page` でページングし、`total` を返す。
+/// `cursor`（最初のページは空文字）を渡すとキーセットページングになり、
+/// `total` は計算せず、続きがある間は `next_cursor` を返す。
+/// `per_page` は1〜100、`page` は1以上で、範囲外は400を返す。
 pub async fn list_posts(
     State(state): State<Arc<AppState>>,
     headers: axum::http::HeaderMap,
     Query(params): Query<ListParams>,
 ) -> Result<Json<PostListResponse>, (StatusCode, String)> {
     let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    let viewer = super::readers::resolve_viewer(&state, &headers, user_id).await?;
+    let owner_id = viewer.owner_id();
+
+    // Readers only see published posts in the notebooks shared with them
+    let notebooks = viewer.readable_notebooks(params.notebook_id)?;
+    let status = match viewer {
+        Viewer::Owner(_) => params.status.unwrap_or_default(),
+        Viewer::Reader(_) => PostStatus::Published,
+    };
 
     let page = params.page.unwrap_or(1);
     let per_page = params.per_page.unwrap_or(10);
+    if !(1..=MAX_PER_PAGE).contains(&per_page) {
+        return Err((StatusCode::BAD_REQUEST, format!("per_page must be between 1 and {}", MAX_PER_PAGE)));
+    }
+    if page < 1 {
+        return Err((StatusCode::BAD_REQUEST, "page must be 1 or greater".to_string()));
+    }
 
-    let (posts, total) = db::get_posts(
-        &state.db,
-        &user_id,
-        page,
-        per_page,
-        params.search.as_deref(),
-        params.date_from.as_deref(),
-        params.date_to.as_deref(),
-    )
-    .await
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    // Encrypted content cannot be searched on the server
+    if params.search.is_some() {
+        super::encryption::ensure_disabled(&state, &owner_id, "Search").await?;
+    }
+
+    let (date_from, date_to) =
+        parse_date_bounds(&state, &owner_id, params.date_from.as_deref(), params.date_to.as_deref()).await?;
+
+    let filters = db::PostFilters {
+        search: params.search.as_deref(),
+        date_from,
+        date_to,
+        tag: params.tag.as_deref(),
+        mood: params.mood.map(Mood::as_str),
+        notebook: params.notebook_id,
+        notebooks,
+        status,
+    };
+
+    if let Some(cursor) = params.cursor.as_deref() {
+        let after = match cursor {
+            "" => None,
+            cursor => Some(decode_cursor(cursor)?),
+        };
+
+        // Fetch one extra row to know whether another page exists
+        let mut posts = db::get_posts_after(&state.db, &owner_id, per_page + 1, after, &filters)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+        let next_cursor = if posts.len() > per_page as usize {
+            posts.truncate(per_page as usize);
+            posts.last().map(encode_cursor)
+        } else {
+            None
+        };
+        log_listed_posts(&state, &viewer, &posts).await?;
+
+        return Ok(Json(PostListResponse {
+            posts,
+            total: None,
+            page: None,
+            per_page,
+            next_cursor,
+        }));
+    }
+
+    let (posts, total) = db::get_posts(&state.db, &owner_id, page, per_page, &filters)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    log_listed_posts(&state, &viewer, &posts).await?;
 
     Ok(Json(PostListResponse {
         posts,
-        total,
-        page,
+        total: Some(total),
+        page: Some(page),
         per_page,
+        next_cursor: None,
     }))
 }
 
+/// 閲覧者が一覧で受け取った投稿を記録する
+async fn log_listed_posts(state: &AppState, viewer: &Viewer, posts: &[Post]) -> Result<(), (StatusCode, String)> {
+    let ids: Vec<Uuid> = posts.iter().map(|post| post.id).collect();
+    viewer.log_access_many(state, "posts", &ids).await
+}
+
 pub async fn create_post(
     State(state): State<Arc<AppState>>,
     headers: axum::http::HeaderMap,
@@ -77,15 +243,45 @@ pub async fn create_post(
 ) -> Result<Json<Post>, (StatusCode, String)> {
     let user_id = extract_user_id(&headers, &state.jwt_secret)?;
 
+    super::moods::validate_intensity(req.mood_intensity)?;
+    if let Some(notebook_id) = req.notebook_id {
+        super::notebooks::ensure_notebook(&state, &notebook_id, &user_id).await?;
+    }
+
+    let encryption = super::encryption::check_post_write(
+        &state,
+        &user_id,
+        req.title.as_deref(),
+        Some(&req.content),
+        req.encryption.as_ref(),
+    )
+    .await?
+    .flatten();
+
+    if let Some(audio_url) = req.audio_url.as_deref() {
+        owned_audio_upload(&state, &user_id, audio_url).await?;
+    }
+
     let image_urls = req.image_urls.unwrap_or_default();
+    let tags = super::tags::normalize_tag_names(&req.tags.unwrap_or_default())?;
 
     let post = db::create_post(
         &state.db,
         &user_id,
-        req.title.as_deref(),
-        &req.content,
-        req.mood.as_deref(),
-        &image_urls,
+        &db::NewPost {
+            title: req.title.as_deref(),
+            content: &req.content,
+            mood: req.mood.map(Mood::as_str),
+            mood_intensity: req.mood_intensity,
+            image_urls: &image_urls,
+            audio_url: req.audio_url.as_deref(),
+            content_format: req.content_format.unwrap_or_default(),
+            status: req.status.unwrap_or_default(),
+            entry_date: req.entry_date,
+            notebook_id: req.notebook_id,
+            encryption: encryption.as_ref(),
+            tags: &tags,
+        },
     )
     .await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -93,43 +289,326 @@ pub async fn create_post(
     Ok(Json(post))
 }
 
-pub async fn get_post(
+/// `audio_url` がユーザー自身のアップロードした音声ファイルか確認する
+async fn owned_audio_upload(state: &AppState, user_id: &Uuid, audio_url: &str) -> Result<Upload, (StatusCode, String)> {
+    let filename = audio_url
+        .strip_prefix("/uploads/")
+        .filter(|filename| !filename.is_empty() && !filename.contains('/'))
+        .ok_or((StatusCode::BAD_REQUEST, "Invalid audio url".to_string()))?;
+
+    let upload = db::get_upload_by_filename(&state.db, user_id, filename)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Audio not found".to_string()))?;
+
+    if !upload.content_type.starts_with("audio/") {
+        return Err((StatusCode::BAD_REQUEST, "audio_url must be an audio file".to_string()));
+    }
+    Ok(upload)
+}
+
+/// アップロードされた音声メモを文字起こしし、音声を添付した投稿を作成する
+pub async fn create_voice_post(
     State(state): State<Arc<AppState>>,
     headers: axum::http::HeaderMap,
-    Path(id): Path<Uuid>,
+    Json(req): Json<CreateVoicePostRequest>,
 ) -> Result<Json<Post>, (StatusCode, String)> {
     let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    super::moods::validate_intensity(req.mood_intensity)?;
+    super::encryption::ensure_disabled(&state, &user_id, "Voice posts").await?;
+
+    let upload = owned_audio_upload(&state, &user_id, &req.audio_url).await?;
 
-    let post = db::get_post_by_id(&state.db, &id, &user_id)
+    let audio = tokio::fs::read(format!("uploads/{}", upload.filename))
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-        .ok_or((StatusCode::NOT_FOUND, "Post not found".to_string()))?;
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let transcript = state
+        .transcriber
+        .transcribe(audio, &upload.filename, &upload.content_type)
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+
+    if transcript.is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, "Transcription is empty".to_string()));
+    }
+
+    let post = db::create_post(
+        &state.db,
+        &user_id,
+        &db::NewPost {
+            title: req.title.as_deref(),
+            content: &transcript,
+            mood: req.mood.map(Mood::as_str),
+            mood_intensity: req.mood_intensity,
+            image_urls: &[],
+            audio_url: Some(&req.audio_url),
+            content_format: ContentFormat::Plain,
+            status: PostStatus::Published,
+            entry_date: None,
+            notebook_id: None,
+            encryption: None,
+            tags: &[],
+        },
+    )
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     Ok(Json(post))
 }
 
+pub async fn get_post(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Response, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    let viewer = super::readers::resolve_viewer(&state, &headers, user_id).await?;
+
+    let mut post = get_readable_post(&state, &viewer, &id).await?;
+    apply_pending_autosave(&state, &user_id, &mut post);
+
+    Ok(([(header::ETAG, etag(&post))], Json(post)).into_response())
+}
+
 pub async fn update_post(
     State(state): State<Arc<AppState>>,
     headers: axum::http::HeaderMap,
     Path(id): Path<Uuid>,
     Json(req): Json<UpdatePostRequest>,
-) -> Result<Json<Post>, (StatusCode, String)> {
+) -> Result<Response, (StatusCode, String)> {
     let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    let expected_version = parse_if_match(&headers)?;
+    super::moods::validate_intensity(req.mood_intensity)?;
+    if let Some(Some(notebook_id)) = req.notebook_id {
+        super::notebooks::ensure_notebook(&state, &notebook_id, &user_id).await?;
+    }
 
-    let post = db::update_post(
-        &state.db,
-        &id,
+    let tags = req
+        .tags
+        .as_deref()
+        .map(super::tags::normalize_tag_names)
+        .transpose()?;
+    let encryption = super::encryption::check_post_write(
+        &state,
         &user_id,
         req.title.as_deref(),
         req.content.as_deref(),
-        req.mood.as_deref(),
-        req.image_urls.as_deref(),
+        req.encryption.as_ref(),
     )
-    .await
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-    .ok_or((StatusCode::NOT_FOUND, "Post not found".to_string()))?;
+    .await?;
 
-    Ok(Json(post))
+    // 明示的な保存は自動保存より優先する。保存できなかった場合は自動保存の内容を戻す
+    let pending = state.autosave.take(&id, &user_id);
+
+    let result = db::update_post(
+        &state.db,
+        &id,
+        &user_id,
+        &db::PostChanges {
+            title: req.title.as_deref(),
+            content: req.content.as_deref(),
+            mood: req.mood.map(Mood::as_str),
+            mood_intensity: req.mood_intensity,
+            image_urls: req.image_urls.as_deref(),
+            content_format: req.content_format,
+            entry_date: req.entry_date,
+            notebook_id: req.notebook_id,
+            encryption: encryption.as_ref().map(Option::as_ref),
+            tags: tags.as_deref(),
+        },
+        expected_version,
+    )
+    .await;
+    if !matches!(result, Ok(Some(_))) {
+        if let Some(draft) = pending {
+            state.autosave.restore(id, draft);
+        }
+    }
+    let post = result.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let Some(post) = post else {
+        // The post was either edited elsewhere or does not exist
+        let current = db::get_post_by_id(&state.db, &id, &user_id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .ok_or((StatusCode::NOT_FOUND, "Post not found".to_string()))?;
+
+        return Ok((
+            StatusCode::PRECONDITION_FAILED,
+            [(header::ETAG, etag(&current))],
+            Json(current),
+        )
+            .into_response());
+    };
+
+    Ok(([(header::ETAG, etag(&post))], Json(post)).into_response())
+}
+
+/// 下書きの内容全体を自動保存として溜める
+///
+/// 書き込み間隔内の保存は1回の書き込みにまとまるため、クライアントは入力が止まるたびに呼び出してよい。
+pub async fn autosave_draft(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(req): Json<AutosaveRequest>,
+) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    // Ownership, status and encryption were already checked if a save is pending
+    if !state.autosave.is_pending(&id, &user_id) {
+        super::encryption::ensure_disabled(&state, &user_id, "Autosave").await?;
+
+        let post = db::get_post_by_id(&state.db, &id, &user_id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .ok_or((StatusCode::NOT_FOUND, "Post not found".to_string()))?;
+
+        if post.status != PostStatus::Draft.as_str() {
+            return Err((StatusCode::CONFLICT, "Only drafts can be autosaved".to_string()));
+        }
+    }
+
+    state.autosave.stage(id, user_id, req.title, req.content);
+
+    Ok((StatusCode::ACCEPTED, Json(serde_json::json!({"message": "Draft saved"}))))
+}
+
+/// 下書きを公開する（投稿日時は公開した時点になり、記録日は下書きのまま残る）
+pub async fn publish_post(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Response, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    if state.autosave.is_pending(&id, &user_id) {
+        state
+            .autosave
+            .flush_post(&state.db, &id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    let post = db::publish_post(&state.db, &id, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let Some(post) = post else {
+        let exists = db::get_post_by_id(&state.db, &id, &user_id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .is_some();
+
+        return Err(if exists {
+            (StatusCode::CONFLICT, "Post is already published".to_string())
+        } else {
+            (StatusCode::NOT_FOUND, "Post not found".to_string())
+        });
+    };
+
+    Ok(([(header::ETAG, etag(&post))], Json(post)).into_response())
+}
+
+/// 下書きに書き込み前の自動保存があれば、その内容を投稿に重ねる
+///
+/// 読み取りのリクエストでは書き込まない。書き込みは定期的な書き込みに任せる。
+fn apply_pending_autosave(state: &AppState, user_id: &Uuid, post: &mut Post) {
+    if let Some((title, content)) = state.autosave.pending_content(&post.id, user_id) {
+        post.title = title;
+        post.content = content;
+    }
+}
+
+/// 閲覧者が読める投稿を取得し、閲覧者の場合は閲覧を記録する
+///
+/// 閲覧を許可されていない投稿は存在しないものとして404を返す。
+pub async fn get_readable_post(state: &AppState, viewer: &Viewer, id: &Uuid) -> Result<Post, (StatusCode, String)> {
+    let post = db::get_post_by_id(&state.db, id, &viewer.owner_id())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .filter(|post| viewer.can_read_post(post))
+        .ok_or((StatusCode::NOT_FOUND, "Post not found".to_string()))?;
+
+    viewer.log_access(state, "post", Some(id)).await?;
+    Ok(post)
+}
+
+/// 投稿の本文を無害化したHTMLにする
+///
+/// 画像は投稿者がアップロードしたものだけを残す。E2EEの投稿はクライアントで表示する。
+pub async fn render_html(state: &AppState, post: &Post) -> Result<String, (StatusCode, String)> {
+    if post.encryption.is_some() {
+        return Err((StatusCode::BAD_REQUEST, "Encrypted posts are rendered on the client".to_string()));
+    }
+
+    let format = ContentFormat::from_db(&post.content_format);
+    let images = render::image_filenames(&post.content, format);
+    let owned_uploads = if images.is_empty() {
+        HashSet::new()
+    } else {
+        db::get_owned_upload_filenames(&state.db, &post.user_id, &images)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .into_iter()
+            .collect()
+    };
+
+    Ok(render::to_html(&post.content, format, &owned_uploads))
+}
+
+/// 投稿の本文をHTMLで返す
+///
+/// どのクライアントでも同じ表示になるよう、サーバーで描画して無害化する。
+/// 下書きは書き込み前の自動保存の内容を描画する。
+pub async fn get_rendered_post(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Response, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    let viewer = super::readers::resolve_viewer(&state, &headers, user_id).await?;
+
+    let mut post = get_readable_post(&state, &viewer, &id).await?;
+    apply_pending_autosave(&state, &user_id, &mut post);
+
+    let rendered = RenderedPost {
+        id: post.id,
+        content_format: ContentFormat::from_db(&post.content_format),
+        rendered_html: render_html(&state, &post).await?,
+        version: post.version,
+    };
+
+    Ok(([(header::ETAG, etag(&post))], Json(rendered)).into_response())
+}
+
+/// 本文でこの投稿にリンクしている公開済みの投稿
+///
+/// 閲覧者には共有されたノートの投稿からのリンクのみを返す。
+pub async fn list_backlinks(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<Backlink>>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    let viewer = super::readers::resolve_viewer(&state, &headers, user_id).await?;
+    let owner_id = viewer.owner_id();
+    let notebooks = viewer.readable_notebooks(None)?;
+
+    db::get_post_by_id(&state.db, &id, &owner_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .filter(|post| viewer.can_read_post(post))
+        .ok_or((StatusCode::NOT_FOUND, "Post not found".to_string()))?;
+
+    let backlinks = db::get_backlinks(&state.db, &id, &owner_id, notebooks)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    viewer.log_access(&state, "backlinks", Some(&id)).await?;
+
+    Ok(Json(backlinks))
 }
 
 pub async fn delete_post(
@@ -139,6 +618,10 @@ pub async fn delete_post(
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     let user_id = extract_user_id(&headers, &state.jwt_secret)?;
 
+    if state.autosave.is_pending(&id, &user_id) {
+        state.autosave.discard(&id);
+    }
+
     let deleted = db::delete_post(&state.db, &id, &user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -149,3 +632,146 @@ pub async fn delete_post(
 
     Ok(Json(serde_json::json!({"message": "Post deleted"})))
 }
+
+/// 複数の投稿に同じ操作を行う
+///
+/// 対象は `ids` か、一覧と同じ絞り込み条件 `filter`（公開済みの投稿のみ）で指定する。
+/// 削除・移動・タグ付け・タグ解除は1つのトランザクションで行い、すべて反映されるか何も変わらない。
+/// 再分析はトランザクションではなく、投稿ごとにAIを順に呼び出して結果を保存する。
+/// 途中の投稿が失敗しても他の投稿の結果は残り、最大 `MAX_BULK_ANALYSES` 件分の時間がかかる。
+/// レスポンスには対象の投稿ごとの結果を含める。
+pub async fn bulk_posts(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Json(req): Json<BulkPostRequest>,
+) -> Result<Json<BulkPostResponse>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    let ids = match (req.ids, req.filter) {
+        (Some(ids), None) => {
+            let mut unique: Vec<Uuid> = Vec::with_capacity(ids.len());
+            for id in ids {
+                if !unique.contains(&id) {
+                    unique.push(id);
+                }
+            }
+            if unique.len() > MAX_BULK_POSTS {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("At most {} posts can be changed at once", MAX_BULK_POSTS),
+                ));
+            }
+            unique
+        }
+        (None, Some(filter)) => {
+            if filter.search.is_some() {
+                super::encryption::ensure_disabled(&state, &user_id, "Search").await?;
+            }
+            let (date_from, date_to) =
+                parse_date_bounds(&state, &user_id, filter.date_from.as_deref(), filter.date_to.as_deref()).await?;
+            let filters = db::PostFilters {
+                search: filter.search.as_deref(),
+                date_from,
+                date_to,
+                tag: filter.tag.as_deref(),
+                ..Default::default()
+            };
+
+            let ids = db::get_matching_post_ids(&state.db, &user_id, MAX_BULK_POSTS as i32 + 1, &filters)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            if ids.len() > MAX_BULK_POSTS {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("Filter matches more than {} posts", MAX_BULK_POSTS),
+                ));
+            }
+            ids
+        }
+        _ => {
+            return Err((StatusCode::BAD_REQUEST, "Specify either ids or filter".to_string()));
+        }
+    };
+
+    let tag_names = match &req.action {
+        BulkAction::Tag { tags } | BulkAction::Untag { tags } => {
+            let names = super::tags::normalize_tag_names(tags)?;
+            if names.is_empty() {
+                return Err((StatusCode::BAD_REQUEST, "Tags are required".to_string()));
+            }
+            names
+        }
+        _ => Vec::new(),
+    };
+
+    let change = match req.action {
+        BulkAction::Delete => db::BulkChange::Delete,
+        BulkAction::Move { notebook_id } => {
+            if let Some(notebook_id) = notebook_id {
+                super::notebooks::ensure_notebook(&state, &notebook_id, &user_id).await?;
+            }
+            db::BulkChange::Move(notebook_id)
+        }
+        BulkAction::Tag { .. } => db::BulkChange::Tag(&tag_names),
+        BulkAction::Untag { .. } => db::BulkChange::Untag(&tag_names),
+        BulkAction::Analyze => {
+            if ids.len() > MAX_BULK_ANALYSES {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("At most {} posts can be analyzed at once", MAX_BULK_ANALYSES),
+                ));
+            }
+            return Ok(Json(bulk_response(analyze_posts(&state, &user_id, &ids).await)));
+        }
+    };
+
+    let changed = db::bulk_update_posts(&state.db, &user_id, &ids, &change)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if matches!(change, db::BulkChange::Delete) {
+        for id in &changed {
+            state.autosave.discard(id);
+        }
+    }
+
+    let results = ids
+        .into_iter()
+        .map(|id| {
+            let ok = changed.contains(&id);
+            BulkItemResult {
+                id,
+                ok,
+                error: (!ok).then(|| "Post not found".to_string()),
+            }
+        })
+        .collect();
+
+    Ok(Json(bulk_response(results)))
+}
+
+async fn analyze_posts(state: &AppState, user_id: &Uuid, ids: &[Uuid]) -> Vec<BulkItemResult> {
+    let mut results = Vec::with_capacity(ids.len());
+    for &id in ids {
+        let outcome = match db::get_post_by_id(&state.db, &id, user_id).await {
+            Ok(Some(post)) => super::analyses::analyze(state, &post, user_id, None).await.map(|_| ()),
+            Ok(None) => Err((StatusCode::NOT_FOUND, "Post not found".to_string())),
+            Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+        };
+        results.push(BulkItemResult {
+            id,
+            ok: outcome.is_ok(),
+            error: outcome.err().map(|(_, message)| message),
+        });
+    }
+    results
+}
+
+fn bulk_response(results: Vec<BulkItemResult>) -> BulkPostResponse {
+    let succeeded = results.iter().filter(|r| r.ok).count();
+    BulkPostResponse {
+        succeeded,
+        failed: results.len() - succeeded,
+        results,
+    }
+}
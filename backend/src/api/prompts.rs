@@ -0,0 +1,142 @@
+//! 問いかけAPI
+//!
+//! 何を書くか迷ったときの問いかけ（ライティングプロンプト）を提供。
+//! - list_prompts: 組み込みの問いかけ一覧（カテゴリで絞り込み可）
+//! - generate_prompts: 最近の分析トピックからAIで問いかけを生成（AI呼び出しあり）
+
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    Json,
+};
+use chrono::Datelike;
+use serde::Deserialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{auth::verify_token, ai, db, models::WritingPrompt, AppState};
+
+/// 組み込みの問いかけ（カテゴリ, 問いかけ）
+const BUILTIN_PROMPTS: &[(&str, &str)] = &[
+    ("gratitude", "今日、ありがたいと感じたことを3つ書いてみましょう。"),
+    ("gratitude", "最近だれかにしてもらって嬉しかったことは何ですか？"),
+    ("gratitude", "当たり前になっているけれど、なくなると困るものは何ですか？"),
+    ("daily_review", "今日いちばん印象に残った出来事は何でしたか？"),
+    ("daily_review", "今日うまくいったことと、次は変えてみたいことは？"),
+    ("daily_review", "今日のエネルギーが上がった瞬間と下がった瞬間は？"),
+    ("emotions", "今の気持ちを天気にたとえると何ですか？その理由は？"),
+    ("emotions", "最近モヤモヤしていることを、そのまま書き出してみましょう。"),
+    ("emotions", "心が落ち着くのはどんなときですか？"),
+    ("goals", "1週間後の自分に向けて、ひとこと書くとしたら？"),
+    ("goals", "今いちばん大切にしたいことは何ですか？"),
+    ("goals", "小さくても、明日やってみたいことを1つ決めましょう。"),
+    ("relationships", "最近話した人の中で、心に残った言葉はありますか？"),
+    ("relationships", "今、連絡を取りたいと思う人はだれですか？"),
+    ("self", "子どもの頃の自分に伝えたいことは何ですか？"),
+    ("self", "自分の好きなところを1つ書いてみましょう。"),
+];
+
+/// 生成する問いかけの数
+const GENERATED_PROMPT_COUNT: usize = 3;
+
+/// トピック収集に使う直近の分析件数
+const RECENT_ANALYSES: i32 = 10;
+
+#[derive(Debug, Deserialize)]
+pub struct ListParams {
+    category: Option<String>,
+}
+
+/// JWTトークンからユーザーIDを抽出する
+fn extract_user_id(headers: &axum::http::HeaderMap, jwt_secret: &str) -> Result<Uuid, (StatusCode, String)> {
+    let auth_header = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, "Missing authorization".to_string()))?;
+
+    let token = auth_header
+        .strip_prefix("Bearer ")
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    let claims = verify_token(token, jwt_secret)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    Uuid::parse_str(&claims.sub)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid user id".to_string()))
+}
+
+fn builtin_prompts(category: Option<&str>) -> Vec<WritingPrompt> {
+    BUILTIN_PROMPTS
+        .iter()
+        .filter(|(c, _)| category.is_none_or(|category| *c == category))
+        .map(|(category, text)| WritingPrompt {
+            category: category.to_string(),
+            text: text.to_string(),
+        })
+        .collect()
+}
+
+pub async fn list_prompts(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Query(params): Query<ListParams>,
+) -> Result<Json<Vec<WritingPrompt>>, (StatusCode, String)> {
+    extract_user_id(&headers, &state.jwt_secret)?;
+
+    Ok(Json(builtin_prompts(params.category.as_deref())))
+}
+
+/// 最近の分析トピックから問いかけを生成するエンドポイント
+///
+/// 分析がない場合やAIが使えない場合は、組み込みの問いかけからいくつか返す。
+pub async fn generate_prompts(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Vec<WritingPrompt>>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    let analyses = db::get_user_analyses(&state.db, &user_id, RECENT_ANALYSES, None)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let mut topics: Vec<String> = Vec::new();
+    for analysis in &analyses {
+        let Some(items) = analysis.result.get("topics").and_then(|t| t.as_array()) else {
+            continue;
+        };
+        for topic in items.iter().filter_map(|t| t.as_str()) {
+            if !topics.iter().any(|t| t == topic) {
+                topics.push(topic.to_string());
+            }
+        }
+    }
+
+    let generated = ai::generate_writing_prompts(&state.gemini_api_key, &topics, GENERATED_PROMPT_COUNT)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if generated.is_empty() {
+        // 日付ごとに違う問いかけになるよう組み込みの一覧からずらして選ぶ
+        let offset = chrono::Utc::now().ordinal0() as usize;
+        let prompts = (0..GENERATED_PROMPT_COUNT)
+            .map(|i| {
+                let (category, text) = BUILTIN_PROMPTS[(offset + i * 5) % BUILTIN_PROMPTS.len()];
+                WritingPrompt {
+                    category: category.to_string(),
+                    text: text.to_string(),
+                }
+            })
+            .collect();
+        return Ok(Json(prompts));
+    }
+
+    Ok(Json(
+        generated
+            .into_iter()
+            .map(|text| WritingPrompt {
+                category: "ai".to_string(),
+                text,
+            })
+            .collect(),
+    ))
+}
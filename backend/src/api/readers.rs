@@ -0,0 +1,358 @@
+//! 閲覧者API
+//!
+//! カウンセラーなど信頼できる別のSoulMapアカウントに、選んだノートの投稿・分析結果、
+//! または分析サマリーだけを読み取り専用で共有する。
+//! 閲覧者は `X-Owner-Id` ヘッダーに共有元のユーザーIDを指定して、閲覧用のエンドポイント
+//! （`READER_ROUTES`）を呼び出す。閲覧した内容は共有元が確認できるよう記録される。
+//! - list_readers / create_reader / update_reader / delete_reader: 共有する閲覧者の管理
+//! - list_shared_with_me: 自分に共有されている一覧
+//! - get_access_log: 閲覧者が見た内容の記録
+//! - resolve_viewer: 閲覧用エンドポイントでの権限の判定
+
+use axum::{
+    extract::{MatchedPath, Path, Query, Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    auth::verify_token,
+    db,
+    models::{CreateReaderGrantRequest, Post, PostStatus, ReaderAccessEntry, ReaderGrant, UpdateReaderGrantRequest},
+    AppState,
+};
+
+/// 共有元のユーザーIDを指定するヘッダー
+pub const OWNER_HEADER: &str = "x-owner-id";
+
+/// 閲覧者が `X-Owner-Id` を付けて呼び出せるエンドポイント（すべてGET）
+///
+/// いずれも `resolve_viewer` で権限を判定し、共有されたノートの公開済み投稿の範囲で返す。
+const READER_ROUTES: &[&str] = &[
+    "/api/v1/posts",
+    "/api/v1/posts/:id",
+    "/api/v1/posts/:id/rendered",
+    "/api/v1/posts/:id/backlinks",
+    "/api/v1/posts/:id/revisions",
+    "/api/v1/posts/:id/revisions/diff",
+    "/api/v1/analyses/post/:post_id",
+    "/api/v1/analyses/user/summary",
+    "/api/v1/calendar",
+    "/api/v1/stats",
+    "/api/v1/moods",
+    "/api/v1/moods/stats",
+    "/api/v1/memories",
+];
+
+/// 閲覧記録の取得件数の上限
+const ACCESS_LOG_LIMIT: i64 = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct AccessLogParams {
+    reader_id: Option<Uuid>,
+}
+
+/// JWTトークンからユーザーIDを抽出する
+fn extract_user_id(headers: &axum::http::HeaderMap, jwt_secret: &str) -> Result<Uuid, (StatusCode, String)> {
+    let auth_header = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, "Missing authorization".to_string()))?;
+
+    let token = auth_header
+        .strip_prefix("Bearer ")
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    let claims = verify_token(token, jwt_secret)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    Uuid::parse_str(&claims.sub)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid user id".to_string()))
+}
+
+/// リクエストで読むデータの持ち主と、閲覧者として読む場合はその権限
+#[derive(Debug)]
+pub enum Viewer {
+    /// 自分のデータ
+    Owner(Uuid),
+    /// 共有されたデータ
+    Reader(ReaderGrant),
+}
+
+impl Viewer {
+    /// 読むデータの持ち主のユーザーID
+    pub fn owner_id(&self) -> Uuid {
+        match self {
+            Viewer::Owner(user_id) => *user_id,
+            Viewer::Reader(grant) => grant.owner_id,
+        }
+    }
+
+    /// 共有されたノートの公開済み投稿のみ閲覧者が読める
+    pub fn can_read_post(&self, post: &Post) -> bool {
+        match self {
+            Viewer::Owner(_) => true,
+            Viewer::Reader(grant) => {
+                post.status == PostStatus::Published.as_str()
+                    && post.notebook_id.is_some_and(|id| grant.notebook_ids.contains(&id))
+            }
+        }
+    }
+
+    /// 投稿一覧の対象にできるノート（閲覧者の場合）
+    ///
+    /// `requested` が共有されていないノートの場合は403。
+    pub fn readable_notebooks(&self, requested: Option<Uuid>) -> Result<Option<&[Uuid]>, (StatusCode, String)> {
+        let Viewer::Reader(grant) = self else {
+            return Ok(None);
+        };
+        if grant.notebook_ids.is_empty() {
+            return Err((StatusCode::FORBIDDEN, "No notebooks are shared with you".to_string()));
+        }
+        if requested.is_some_and(|id| !grant.notebook_ids.contains(&id)) {
+            return Err((StatusCode::FORBIDDEN, "This notebook is not shared with you".to_string()));
+        }
+        Ok(Some(&grant.notebook_ids))
+    }
+
+    /// 集計や一覧の対象にするノート（`None` はすべて）
+    ///
+    /// 自分のデータは `requested` のノートのみ（指定がなければすべて）。
+    /// 閲覧者は共有されたノートのうち `requested` のノートのみ（指定がなければ共有されたすべて）で、
+    /// 共有されていないノートを指定した場合やノートが共有されていない場合は403。
+    pub fn notebook_scope(&self, requested: Option<Uuid>) -> Result<Option<Vec<Uuid>>, (StatusCode, String)> {
+        let readable = self.readable_notebooks(requested)?;
+        Ok(match (requested, readable) {
+            (Some(id), _) => Some(vec![id]),
+            (None, readable) => readable.map(<[Uuid]>::to_vec),
+        })
+    }
+
+    /// 分析サマリーの対象にするノート（`None` はすべて）
+    ///
+    /// ノートも共有している閲覧者は共有されたノートの範囲（[`Viewer::notebook_scope`] と同じ）。
+    /// 分析サマリーだけを共有している閲覧者はすべての投稿が対象で、ノートは指定できない。
+    pub fn summary_notebook_scope(&self, requested: Option<Uuid>) -> Result<Option<Vec<Uuid>>, (StatusCode, String)> {
+        self.require_analysis_summaries()?;
+        match self {
+            Viewer::Reader(grant) if grant.notebook_ids.is_empty() => match requested {
+                Some(_) => Err((StatusCode::FORBIDDEN, "This notebook is not shared with you".to_string())),
+                None => Ok(None),
+            },
+            _ => self.notebook_scope(requested),
+        }
+    }
+
+    /// 閲覧者が投稿を読めるか確認する（自分のデータの場合は確認しない）
+    ///
+    /// 読めない投稿は存在しないものとして404を返す。
+    pub async fn ensure_can_read_post(&self, state: &AppState, post_id: &Uuid) -> Result<(), (StatusCode, String)> {
+        if let Viewer::Reader(_) = self {
+            db::get_post_by_id(&state.db, post_id, &self.owner_id())
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+                .filter(|post| self.can_read_post(post))
+                .ok_or((StatusCode::NOT_FOUND, "Post not found".to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// 分析サマリーを読めるか
+    pub fn require_analysis_summaries(&self) -> Result<(), (StatusCode, String)> {
+        match self {
+            Viewer::Reader(grant) if !grant.analysis_summaries => Err((
+                StatusCode::FORBIDDEN,
+                "Analysis summaries are not shared with you".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// 閲覧者として読んだ場合に閲覧記録を残す
+    pub async fn log_access(&self, state: &AppState, resource: &str, resource_id: Option<&Uuid>) -> Result<(), (StatusCode, String)> {
+        if let Viewer::Reader(grant) = self {
+            db::log_reader_access(&state.db, &grant.owner_id, &grant.reader_id, resource, resource_id)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// 閲覧者として一覧を読んだ場合に、返した項目ごとに閲覧記録を残す
+    pub async fn log_access_many(&self, state: &AppState, resource: &str, resource_ids: &[Uuid]) -> Result<(), (StatusCode, String)> {
+        if let Viewer::Reader(grant) = self {
+            if resource_ids.is_empty() {
+                return Ok(());
+            }
+            db::log_reader_access_many(&state.db, &grant.owner_id, &grant.reader_id, resource, resource_ids)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+/// `X-Owner-Id` ヘッダーから、認証済みユーザーが誰のデータを読むかを判定する
+///
+/// ヘッダーがない（または自分のIDの）場合は自分のデータ。
+/// 共有されていない相手を指定した場合は403。
+pub async fn resolve_viewer(state: &AppState, headers: &HeaderMap, user_id: Uuid) -> Result<Viewer, (StatusCode, String)> {
+    let Some(value) = headers.get(OWNER_HEADER) else {
+        return Ok(Viewer::Owner(user_id));
+    };
+    let owner_id = value
+        .to_str()
+        .ok()
+        .and_then(|v| Uuid::parse_str(v.trim()).ok())
+        .ok_or((StatusCode::BAD_REQUEST, "Invalid X-Owner-Id header".to_string()))?;
+    if owner_id == user_id {
+        return Ok(Viewer::Owner(user_id));
+    }
+
+    let grant = db::get_reader_grant(&state.db, &owner_id, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::FORBIDDEN, "Nothing is shared with you by this user".to_string()))?;
+
+    Ok(Viewer::Reader(grant))
+}
+
+/// 閲覧用以外のエンドポイントへの `X-Owner-Id` 付きリクエストを拒否するミドルウェア
+///
+/// 他のハンドラーは認証済みユーザー自身のデータしか扱わないため、
+/// ヘッダーを無視して自分のデータを返したり書き込んだりしないようにする。
+pub async fn reject_owner_header(matched_path: Option<MatchedPath>, request: Request, next: Next) -> Response {
+    if request.headers().contains_key(OWNER_HEADER) {
+        let readable = request.method() == Method::GET
+            && matched_path.is_some_and(|path| READER_ROUTES.contains(&path.as_str()));
+        if !readable {
+            return (StatusCode::FORBIDDEN, "Shared access is read-only and not available for this endpoint").into_response();
+        }
+    }
+    next.run(request).await
+}
+
+/// 共有するノートが所有者のものか確認する
+async fn ensure_notebooks(state: &AppState, notebook_ids: &[Uuid], owner_id: &Uuid) -> Result<(), (StatusCode, String)> {
+    for notebook_id in notebook_ids {
+        super::notebooks::ensure_notebook(state, notebook_id, owner_id).await?;
+    }
+    Ok(())
+}
+
+pub async fn list_readers(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Vec<ReaderGrant>>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    let grants = db::get_reader_grants_by_owner(&state.db, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(grants))
+}
+
+/// 閲覧者を追加する
+///
+/// メールアドレスからアカウントの有無を調べられないよう、登録されていないメールアドレスでも
+/// 追加できた場合と同じ202を返す。追加された閲覧者は `list_readers` で確認できる。
+pub async fn create_reader(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Json(req): Json<CreateReaderGrantRequest>,
+) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    ensure_notebooks(&state, &req.notebook_ids, &user_id).await?;
+
+    let accepted = (
+        StatusCode::ACCEPTED,
+        Json(serde_json::json!({"message": "The reader will be added if the account exists"})),
+    );
+
+    let reader = db::get_user_by_email(&state.db, req.reader_email.trim())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let Some(reader) = reader else {
+        return Ok(accepted);
+    };
+    if reader.id == user_id {
+        return Err((StatusCode::BAD_REQUEST, "You cannot share with yourself".to_string()));
+    }
+
+    db::create_reader_grant(&state.db, &user_id, &reader.id, &req.notebook_ids, req.analysis_summaries)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::CONFLICT, "Already shared with this user".to_string()))?;
+
+    Ok(accepted)
+}
+
+pub async fn update_reader(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(req): Json<UpdateReaderGrantRequest>,
+) -> Result<Json<ReaderGrant>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    ensure_notebooks(&state, &req.notebook_ids, &user_id).await?;
+
+    let grant = db::update_reader_grant(&state.db, &id, &user_id, &req.notebook_ids, req.analysis_summaries)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Reader not found".to_string()))?;
+
+    Ok(Json(grant))
+}
+
+/// 共有を取り消す（閲覧記録は残る）
+pub async fn delete_reader(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    let deleted = db::delete_reader_grant(&state.db, &id, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if !deleted {
+        return Err((StatusCode::NOT_FOUND, "Reader not found".to_string()));
+    }
+
+    Ok(Json(serde_json::json!({"message": "Reader removed"})))
+}
+
+pub async fn list_shared_with_me(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Vec<ReaderGrant>>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    let grants = db::get_reader_grants_for_reader(&state.db, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(grants))
+}
+
+/// 閲覧者が見た内容の記録（新しい順、`reader_id` で絞り込み可）
+pub async fn get_access_log(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Query(params): Query<AccessLogParams>,
+) -> Result<Json<Vec<ReaderAccessEntry>>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    let entries = db::get_reader_access_log(&state.db, &user_id, params.reader_id.as_ref(), ACCESS_LOG_LIMIT)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(entries))
+}
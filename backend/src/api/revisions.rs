@@ -0,0 +1,190 @@
+//! 投稿の変更履歴API
+//!
+//! 投稿の作成・更新のたびに保存されるリビジョンの参照と復元を提供。
+//! - list_revisions: リビジョン一覧
+//! - diff_revisions: 2つのリビジョンの差分（暗号化されたリビジョンはクライアントで比較する）
+//! - restore_revision: 指定リビジョンの内容に戻す
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use similar::{ChangeTag, TextDiff};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    auth::verify_token,
+    db,
+    models::{DiffLine, FieldChange, PostRevision, RevisionDiff},
+    AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct DiffParams {
+    from: i32,
+    to: i32,
+}
+
+/// JWTトークンからユーザーIDを抽出する
+fn extract_user_id(headers: &axum::http::HeaderMap, jwt_secret: &str) -> Result<Uuid, (StatusCode, String)> {
+    let auth_header = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, "Missing authorization".to_string()))?;
+
+    let token = auth_header
+        .strip_prefix("Bearer ")
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    let claims = verify_token(token, jwt_secret)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    Uuid::parse_str(&claims.sub)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid user id".to_string()))
+}
+
+fn field_change<T: PartialEq + Clone>(from: &T, to: &T) -> Option<FieldChange<T>> {
+    (from != to).then(|| FieldChange {
+        from: from.clone(),
+        to: to.clone(),
+    })
+}
+
+fn image_urls(revision: &PostRevision) -> Vec<String> {
+    serde_json::from_value(revision.image_urls.clone()).unwrap_or_default()
+}
+
+/// 2つのリビジョンの差分を計算する（本文は行単位）
+fn diff(from: &PostRevision, to: &PostRevision) -> RevisionDiff {
+    let from_images = image_urls(from);
+    let to_images = image_urls(to);
+
+    let content = TextDiff::from_lines(&from.content, &to.content)
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            op: match change.tag() {
+                ChangeTag::Equal => "equal",
+                ChangeTag::Insert => "insert",
+                ChangeTag::Delete => "delete",
+            }
+            .to_string(),
+            text: change.value().to_string(),
+        })
+        .collect();
+
+    RevisionDiff {
+        from_revision: from.revision_number,
+        to_revision: to.revision_number,
+        title: field_change(&from.title, &to.title),
+        mood: field_change(&from.mood, &to.mood),
+        mood_intensity: field_change(&from.mood_intensity, &to.mood_intensity),
+        images_added: to_images.iter().filter(|url| !from_images.contains(url)).cloned().collect(),
+        images_removed: from_images.iter().filter(|url| !to_images.contains(url)).cloned().collect(),
+        content,
+    }
+}
+
+/// 投稿のリビジョン一覧を新しい順に返す
+///
+/// 閲覧者は読める投稿（共有されたノートの公開済み投稿）のリビジョンのみ取得できる。
+pub async fn list_revisions(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(post_id): Path<Uuid>,
+) -> Result<Json<Vec<PostRevision>>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    let viewer = super::readers::resolve_viewer(&state, &headers, user_id).await?;
+    viewer.ensure_can_read_post(&state, &post_id).await?;
+
+    let revisions = db::get_post_revisions(&state.db, &post_id, &viewer.owner_id())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if revisions.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "Post not found".to_string()));
+    }
+
+    viewer.log_access(&state, "revisions", Some(&post_id)).await?;
+
+    Ok(Json(revisions))
+}
+
+/// `from` から `to` への差分を返す（閲覧者は読める投稿のみ）
+pub async fn diff_revisions(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(post_id): Path<Uuid>,
+    Query(params): Query<DiffParams>,
+) -> Result<Json<RevisionDiff>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    let viewer = super::readers::resolve_viewer(&state, &headers, user_id).await?;
+    viewer.ensure_can_read_post(&state, &post_id).await?;
+    let owner_id = viewer.owner_id();
+
+    let from = db::get_post_revision(&state.db, &post_id, &owner_id, params.from)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Revision not found".to_string()))?;
+
+    let to = db::get_post_revision(&state.db, &post_id, &owner_id, params.to)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Revision not found".to_string()))?;
+
+    if from.encryption.is_some() || to.encryption.is_some() {
+        return Err((StatusCode::BAD_REQUEST, "Encrypted revisions are compared on the client".to_string()));
+    }
+
+    viewer.log_access(&state, "revisions", Some(&post_id)).await?;
+
+    Ok(Json(diff(&from, &to)))
+}
+
+/// 投稿を指定したリビジョンの内容に戻す
+///
+/// 更新と同じく `If-Match` が必要で、バージョンが一致しなければ412と現在の投稿を返す。
+pub async fn restore_revision(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path((post_id, revision_number)): Path<(Uuid, i32)>,
+) -> Result<Response, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    let expected_version = super::posts::parse_if_match(&headers)?;
+
+    // 暗号化の設定と異なる状態（有効なのに平文など）の内容には戻さない
+    let revision = db::get_post_revision(&state.db, &post_id, &user_id, revision_number)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Revision not found".to_string()))?;
+    if revision.encryption.is_some() != super::encryption::is_enabled(&state, &user_id).await? {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "This revision does not match the current encryption setting".to_string(),
+        ));
+    }
+
+    let post = db::restore_post_revision(&state.db, &post_id, &user_id, revision_number, expected_version)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let Some(post) = post else {
+        // リビジョンは確認済みのため、他で更新されたか投稿が削除された
+        let current = db::get_post_by_id(&state.db, &post_id, &user_id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .ok_or((StatusCode::NOT_FOUND, "Revision not found".to_string()))?;
+
+        return Ok((
+            StatusCode::PRECONDITION_FAILED,
+            [(header::ETAG, super::posts::etag(&current))],
+            Json(current),
+        )
+            .into_response());
+    };
+
+    Ok(([(header::ETAG, super::posts::etag(&post))], Json(post)).into_response())
+}
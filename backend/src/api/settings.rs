@@ -1,8 +1,20 @@
-use axum::{extract::State, Json};
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    Json,
+};
+use chrono_tz::Tz;
 use serde::Serialize;
 use std::sync::Arc;
+use uuid::Uuid;
 
-use crate::AppState;
+use crate::{
+    auth::verify_token,
+    db,
+    models::{UpdatePreferencesRequest, UserPreferences},
+    timezone,
+    AppState,
+};
 
 #[derive(Debug, Serialize)]
 pub struct Model {
@@ -10,6 +22,7 @@ pub struct Model {
     name: String,
     provider: String,
     description: String,
+    supports_vision: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -18,6 +31,23 @@ pub struct ModelsResponse {
     current: String,
 }
 
+fn extract_user_id(headers: &axum::http::HeaderMap, jwt_secret: &str) -> Result<Uuid, (StatusCode, String)> {
+    let auth_header = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, "Missing authorization".to_string()))?;
+
+    let token = auth_header
+        .strip_prefix("Bearer ")
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    let claims = verify_token(token, jwt_secret)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    Uuid::parse_str(&claims.sub)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid user id".to_string()))
+}
+
 pub async fn get_models(
     State(state): State<Arc<AppState>>,
 ) -> Json<ModelsResponse> {
@@ -29,6 +59,8 @@ pub async fn get_models(
             name: "Gemini Flash".to_string(),
             provider: "google".to_string(),
             description: "高速・無料".to_string(),
+            // 画像分析は `analyze_images` の設定だけで決まり、このモデルは画像入力に対応している
+            supports_vision: true,
         });
     }
 
@@ -37,3 +69,48 @@ pub async fn get_models(
         current: "gemini-flash-latest".to_string(),
     })
 }
+
+pub async fn get_preferences(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<UserPreferences>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    let preferences = db::get_user_preferences(&state.db, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "User not found".to_string()))?;
+
+    Ok(Json(preferences))
+}
+
+pub async fn update_preferences(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Json(req): Json<UpdatePreferencesRequest>,
+) -> Result<Json<UserPreferences>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    if let Some(time_zone) = req.time_zone.as_deref() {
+        if timezone::parse(time_zone).is_none() {
+            return Err((StatusCode::BAD_REQUEST, format!("Unknown time zone: {}", time_zone)));
+        }
+    }
+
+    let preferences = db::update_user_preferences(&state.db, &user_id, req.analyze_images, req.time_zone.as_deref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "User not found".to_string()))?;
+
+    Ok(Json(preferences))
+}
+
+/// The user's time zone, used for "today", date filters and daily aggregates.
+pub async fn user_time_zone(state: &AppState, user_id: &Uuid) -> Result<Tz, (StatusCode, String)> {
+    let time_zone = db::get_user_time_zone(&state.db, user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "User not found".to_string()))?;
+
+    Ok(timezone::resolve(&time_zone))
+}
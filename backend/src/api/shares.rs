@@ -0,0 +1,290 @@
+//! 共有リンクAPI
+//!
+//! カウンセラーや友人に1件の投稿だけを見せるための、期限付きで取り消せる読み取り専用リンク。
+//! トークンは作成時にのみ返し、DBにはハッシュだけを保存する。
+//! - create_share_link / list_share_links: 投稿の共有リンクの作成・一覧
+//! - revoke_share_link: 共有リンクの無効化
+//! - list_share_views: 共有リンクの閲覧記録
+//! - view_shared_post: 認証なしで共有された投稿を表示（閲覧ごとに記録）
+
+use axum::{
+    extract::{ConnectInfo, Path, State},
+    http::{header, HeaderMap, StatusCode},
+    Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    auth::{hash_password, verify_password, verify_token},
+    db,
+    models::{
+        ContentFormat, CreateShareLinkRequest, CreatedShareLink, PostStatus, ShareLink, ShareLinkView, SharedPost,
+    },
+    AppState,
+};
+
+/// 有効期間の既定値（日数）
+const DEFAULT_EXPIRES_IN_DAYS: i64 = 7;
+
+/// 有効期間の上限（日数）
+const MAX_EXPIRES_IN_DAYS: i64 = 90;
+
+/// 共有リンクのパスワードを受け取るヘッダー
+const SHARE_PASSWORD_HEADER: &str = "x-share-password";
+
+/// パスワード付きの共有リンク1件あたり、`FAILED_ATTEMPT_WINDOW_MINUTES` 分間に許す失敗の回数
+const MAX_FAILED_ATTEMPTS: i64 = 10;
+
+/// 失敗の回数を数える期間（分）
+const FAILED_ATTEMPT_WINDOW_MINUTES: i32 = 15;
+
+/// JWTトークンからユーザーIDを抽出する
+fn extract_user_id(headers: &axum::http::HeaderMap, jwt_secret: &str) -> Result<Uuid, (StatusCode, String)> {
+    let auth_header = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, "Missing authorization".to_string()))?;
+
+    let token = auth_header
+        .strip_prefix("Bearer ")
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    let claims = verify_token(token, jwt_secret)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    Uuid::parse_str(&claims.sub)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid user id".to_string()))
+}
+
+/// 推測できないトークンを生成する（UUIDv4 2つ分の乱数）
+fn generate_token() -> String {
+    let mut bytes = Vec::with_capacity(32);
+    bytes.extend_from_slice(Uuid::new_v4().as_bytes());
+    bytes.extend_from_slice(Uuid::new_v4().as_bytes());
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn header_value<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|h| h.to_str().ok())
+}
+
+/// 閲覧者のIPアドレス
+///
+/// 接続元のアドレスを使う。`TRUST_PROXY_HEADERS` が有効な場合（リバースプロキシの背後）だけ、
+/// プロキシが `X-Forwarded-For` の末尾に追加した接続元か `X-Real-IP` を使う。
+/// `X-Forwarded-For` の先頭はクライアントが自由に付けられるため使わない。
+fn client_ip(headers: &HeaderMap, peer: SocketAddr, trust_proxy_headers: bool) -> String {
+    if trust_proxy_headers {
+        let forwarded = header_value(headers, "x-forwarded-for")
+            .and_then(|value| value.rsplit(',').next())
+            .map(str::trim)
+            .filter(|ip| !ip.is_empty())
+            .or_else(|| header_value(headers, "x-real-ip"));
+        if let Some(ip) = forwarded {
+            return ip.to_string();
+        }
+    }
+    peer.ip().to_string()
+}
+
+pub async fn create_share_link(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(post_id): Path<Uuid>,
+    Json(req): Json<CreateShareLinkRequest>,
+) -> Result<Json<CreatedShareLink>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    let expires_in_days = req.expires_in_days.unwrap_or(DEFAULT_EXPIRES_IN_DAYS);
+    if !(1..=MAX_EXPIRES_IN_DAYS).contains(&expires_in_days) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("expires_in_days must be between 1 and {}", MAX_EXPIRES_IN_DAYS),
+        ));
+    }
+
+    let post = db::get_post_by_id(&state.db, &post_id, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Post not found".to_string()))?;
+
+    if post.status == PostStatus::Draft.as_str() {
+        return Err((StatusCode::BAD_REQUEST, "Drafts cannot be shared".to_string()));
+    }
+    if post.encryption.is_some() {
+        return Err((StatusCode::BAD_REQUEST, "Encrypted posts cannot be shared".to_string()));
+    }
+
+    let password_hash = match req.password.as_deref().filter(|p| !p.is_empty()) {
+        Some(password) => Some(
+            hash_password(password).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?,
+        ),
+        None => None,
+    };
+
+    let token = generate_token();
+    let link = db::create_share_link(
+        &state.db,
+        &post_id,
+        &user_id,
+        &hash_token(&token),
+        password_hash.as_deref(),
+        req.include_analysis.unwrap_or(false),
+        Utc::now() + Duration::days(expires_in_days),
+    )
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(CreatedShareLink { link, token }))
+}
+
+pub async fn list_share_links(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(post_id): Path<Uuid>,
+) -> Result<Json<Vec<ShareLink>>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    let links = db::get_share_links(&state.db, &post_id, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(links))
+}
+
+pub async fn revoke_share_link(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    let revoked = db::revoke_share_link(&state.db, &id, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if !revoked {
+        return Err((StatusCode::NOT_FOUND, "Share link not found".to_string()));
+    }
+
+    Ok(Json(serde_json::json!({"message": "Share link revoked"})))
+}
+
+pub async fn list_share_views(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<ShareLinkView>>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    db::get_share_link(&state.db, &id, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Share link not found".to_string()))?;
+
+    let views = db::get_share_link_views(&state.db, &id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(views))
+}
+
+/// 共有された投稿を表示する公開エンドポイント（認証不要）
+///
+/// 存在しない・期限切れ・無効化されたリンクはすべて404を返す。
+/// パスワード付きのリンクは `X-Share-Password` ヘッダーが一致しない場合401を返し、
+/// 失敗した試行も閲覧記録に残す。パスワードの総当たりを防ぐため、失敗が
+/// `FAILED_ATTEMPT_WINDOW_MINUTES` 分間に `MAX_FAILED_ATTEMPTS` 回に達したリンクは
+/// パスワードを照合せずに429を返す。
+pub async fn view_shared_post(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: axum::http::HeaderMap,
+    Path(token): Path<String>,
+) -> Result<Json<SharedPost>, (StatusCode, String)> {
+    let not_found = || (StatusCode::NOT_FOUND, "Share link not found".to_string());
+
+    let access = db::find_active_share_link(&state.db, &hash_token(&token))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(not_found)?;
+
+    let ip_address = client_ip(&headers, peer, state.trust_proxy_headers);
+    let user_agent = header_value(&headers, header::USER_AGENT.as_str());
+
+    match access.password_hash.as_deref() {
+        Some(hash) => {
+            let view_id = db::begin_share_attempt(
+                &state.db,
+                &access.id,
+                MAX_FAILED_ATTEMPTS,
+                FAILED_ATTEMPT_WINDOW_MINUTES,
+                Some(&ip_address),
+                user_agent,
+            )
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .ok_or((
+                StatusCode::TOO_MANY_REQUESTS,
+                "Too many failed attempts, try again later".to_string(),
+            ))?;
+
+            let granted = match header_value(&headers, SHARE_PASSWORD_HEADER) {
+                Some(password) => verify_password(password, hash)
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?,
+                None => false,
+            };
+            if !granted {
+                return Err((StatusCode::UNAUTHORIZED, "Password required".to_string()));
+            }
+
+            db::grant_share_view(&state.db, &view_id)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        }
+        None => {
+            db::record_share_view(&state.db, &access.id, true, Some(&ip_address), user_agent)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        }
+    }
+
+    // ゴミ箱に移動した投稿と、共有後に暗号化された投稿は表示しない
+    let post = db::get_post_by_id(&state.db, &access.post_id, &access.user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .filter(|post| post.encryption.is_none())
+        .ok_or_else(not_found)?;
+
+    let analysis = if access.include_analysis {
+        db::get_analysis_by_post(&state.db, &post.id, &access.user_id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .map(|a| a.result)
+    } else {
+        None
+    };
+
+    let rendered_html = super::posts::render_html(&state, &post).await?;
+
+    Ok(Json(SharedPost {
+        title: post.title,
+        content: post.content,
+        content_format: ContentFormat::from_db(&post.content_format),
+        rendered_html,
+        mood: post.mood,
+        mood_intensity: post.mood_intensity,
+        image_urls: post.image_urls,
+        entry_date: post.entry_date,
+        analysis,
+    }))
+}
@@ -0,0 +1,89 @@
+//! 統計API
+//!
+//! ダッシュボード向けの執筆統計。日付・曜日・時間帯はユーザーのタイムゾーンで数える。
+//! - get_writing_stats: 連続日数、単語数・文字数、よく書く曜日・時間帯、月ごとの投稿数（`notebook_id` でノート単位、閲覧者には共有されたノートのみ）
+
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{auth::verify_token, db, models::WritingStats, timezone, AppState};
+
+#[derive(Debug, Deserialize)]
+pub struct StatsParams {
+    /// 指定した場合はそのノートの投稿のみ
+    notebook_id: Option<Uuid>,
+}
+
+/// JWTトークンからユーザーIDを抽出する
+fn extract_user_id(headers: &axum::http::HeaderMap, jwt_secret: &str) -> Result<Uuid, (StatusCode, String)> {
+    let auth_header = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, "Missing authorization".to_string()))?;
+
+    let token = auth_header
+        .strip_prefix("Bearer ")
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    let claims = verify_token(token, jwt_secret)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    Uuid::parse_str(&claims.sub)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid user id".to_string()))
+}
+
+pub async fn get_writing_stats(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Query(params): Query<StatsParams>,
+) -> Result<Json<WritingStats>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    let viewer = super::readers::resolve_viewer(&state, &headers, user_id).await?;
+    let user_id = viewer.owner_id();
+    let notebooks = viewer.notebook_scope(params.notebook_id)?;
+    let notebook = notebooks.as_deref();
+
+    let time_zone = super::settings::user_time_zone(&state, &user_id).await?;
+
+    let totals = db::get_writing_totals(&state.db, &user_id, notebook)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let (current_streak, longest_streak) = db::get_streaks(&state.db, &user_id, time_zone.name(), timezone::today(time_zone), notebook)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let (most_active_weekday, most_active_hour) = db::get_most_active_times(&state.db, &user_id, time_zone.name(), notebook)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let entries_per_month = db::get_monthly_counts(&state.db, &user_id, time_zone.name(), notebook)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    viewer.log_access(&state, "stats", None).await?;
+
+    let average = |total: i64| match totals.counted_entries {
+        0 => 0.0,
+        entries => total as f64 / entries as f64,
+    };
+
+    Ok(Json(WritingStats {
+        total_entries: totals.total_entries,
+        total_words: totals.total_words,
+        total_characters: totals.total_characters,
+        average_words: average(totals.total_words),
+        average_characters: average(totals.total_characters),
+        current_streak,
+        longest_streak,
+        most_active_weekday,
+        most_active_hour,
+        entries_per_month,
+    }))
+}
@@ -0,0 +1,168 @@
+//! タグ管理API
+//!
+//! ユーザー定義タグの作成・変更・削除と、分析結果のトピックからのタグ付けを提供。
+//! - list_tags: タグ一覧（使用数付き）
+//! - create_tag / rename_tag / delete_tag: タグのCRUD
+//! - tag_from_analysis: 投稿の分析トピックをタグとして付与
+
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    Json,
+};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    auth::verify_token,
+    db,
+    models::{Tag, TagRequest, TagWithCount},
+    AppState,
+};
+
+/// タグ名の最大文字数
+const MAX_TAG_LENGTH: usize = 50;
+
+/// JWTトークンからユーザーIDを抽出する
+fn extract_user_id(headers: &axum::http::HeaderMap, jwt_secret: &str) -> Result<Uuid, (StatusCode, String)> {
+    let auth_header = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, "Missing authorization".to_string()))?;
+
+    let token = auth_header
+        .strip_prefix("Bearer ")
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    let claims = verify_token(token, jwt_secret)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    Uuid::parse_str(&claims.sub)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid user id".to_string()))
+}
+
+/// タグ名を正規化する（前後の空白除去、空文字・重複の除外）
+pub fn normalize_tag_names(names: &[String]) -> Result<Vec<String>, (StatusCode, String)> {
+    let mut normalized: Vec<String> = Vec::new();
+    for name in names {
+        let name = name.trim();
+        if name.is_empty() {
+            continue;
+        }
+        if name.chars().count() > MAX_TAG_LENGTH {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Tag name must be at most {} characters", MAX_TAG_LENGTH),
+            ));
+        }
+        if !normalized.iter().any(|n| n == name) {
+            normalized.push(name.to_string());
+        }
+    }
+    Ok(normalized)
+}
+
+fn normalize_tag_name(name: &str) -> Result<String, (StatusCode, String)> {
+    normalize_tag_names(&[name.to_string()])?
+        .pop()
+        .ok_or((StatusCode::BAD_REQUEST, "Tag name is required".to_string()))
+}
+
+/// タグ一覧を使用数の多い順に返す
+pub async fn list_tags(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Vec<TagWithCount>>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    let tags = db::get_tags_with_counts(&state.db, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(tags))
+}
+
+pub async fn create_tag(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Json(req): Json<TagRequest>,
+) -> Result<Json<Tag>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    let name = normalize_tag_name(&req.name)?;
+
+    let tag = db::create_tag(&state.db, &user_id, &name)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::CONFLICT, "Tag already exists".to_string()))?;
+
+    Ok(Json(tag))
+}
+
+pub async fn rename_tag(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(req): Json<TagRequest>,
+) -> Result<Json<Tag>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    let name = normalize_tag_name(&req.name)?;
+
+    let tag = db::rename_tag(&state.db, &id, &user_id, &name)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db_err) if db_err.is_unique_violation() => {
+                (StatusCode::CONFLICT, "Tag already exists".to_string())
+            }
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?
+        .ok_or((StatusCode::NOT_FOUND, "Tag not found".to_string()))?;
+
+    Ok(Json(tag))
+}
+
+pub async fn delete_tag(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    let deleted = db::delete_tag(&state.db, &id, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if !deleted {
+        return Err((StatusCode::NOT_FOUND, "Tag not found".to_string()));
+    }
+
+    Ok(Json(serde_json::json!({"message": "Tag deleted"})))
+}
+
+/// 投稿の最新の分析結果に含まれるトピックをタグとして付与する
+///
+/// 既存のタグは残したまま追加する。付与後のタグ名一覧を返す。
+pub async fn tag_from_analysis(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(post_id): Path<Uuid>,
+) -> Result<Json<Vec<String>>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    let analysis = db::get_analysis_by_post(&state.db, &post_id, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Analysis not found".to_string()))?;
+
+    let topics: Vec<String> = analysis
+        .result
+        .get("topics")
+        .and_then(|t| serde_json::from_value(t.clone()).ok())
+        .unwrap_or_default();
+    let names = normalize_tag_names(&topics)?;
+
+    let tags = db::add_post_tags(&state.db, &post_id, &user_id, &names)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(tags))
+}
@@ -0,0 +1,238 @@
+//! テンプレートAPI
+//!
+//! 「感謝日記」「一日の振り返り」などの書き出しをテンプレートとして保存し、
+//! そこから下書きを作成する。本文とタイトルの `{{date}}` `{{weekday}}` `{{mood}}` は
+//! 作成時に日記の日付・曜日・気分のラベルに置き換えられる。
+//! - list_templates: テンプレート一覧
+//! - create_template / update_template / delete_template: テンプレートのCRUD
+//! - instantiate_template: テンプレートから下書きを作成
+
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    Json,
+};
+use chrono::{Datelike, NaiveDate, NaiveTime, Weekday};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    auth::verify_token,
+    db,
+    models::{ContentFormat, InstantiateTemplateRequest, Mood, Post, PostStatus, Template, TemplateRequest},
+    timezone, AppState,
+};
+
+/// テンプレート名の最大文字数
+const MAX_NAME_LENGTH: usize = 50;
+
+/// JWTトークンからユーザーIDを抽出する
+fn extract_user_id(headers: &axum::http::HeaderMap, jwt_secret: &str) -> Result<Uuid, (StatusCode, String)> {
+    let auth_header = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, "Missing authorization".to_string()))?;
+
+    let token = auth_header
+        .strip_prefix("Bearer ")
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    let claims = verify_token(token, jwt_secret)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+
+    Uuid::parse_str(&claims.sub)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid user id".to_string()))
+}
+
+fn normalize_name(name: &str) -> Result<&str, (StatusCode, String)> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Template name is required".to_string()));
+    }
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Template name must be at most {} characters", MAX_NAME_LENGTH),
+        ));
+    }
+    Ok(name)
+}
+
+fn weekday_label(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "月曜日",
+        Weekday::Tue => "火曜日",
+        Weekday::Wed => "水曜日",
+        Weekday::Thu => "木曜日",
+        Weekday::Fri => "金曜日",
+        Weekday::Sat => "土曜日",
+        Weekday::Sun => "日曜日",
+    }
+}
+
+/// プレースホルダーを置き換える（気分が未指定なら `{{mood}}` は空になる）
+fn fill_placeholders(text: &str, date: NaiveDate, mood_label: Option<&str>) -> String {
+    text.replace("{{date}}", &date.format("%Y-%m-%d").to_string())
+        .replace("{{weekday}}", weekday_label(date.weekday()))
+        .replace("{{mood}}", mood_label.unwrap_or(""))
+}
+
+pub async fn list_templates(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Vec<Template>>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    let templates = db::get_templates(&state.db, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(templates))
+}
+
+pub async fn create_template(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Json(req): Json<TemplateRequest>,
+) -> Result<Json<Template>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    let name = normalize_name(&req.name)?;
+
+    let template = db::create_template(
+        &state.db,
+        &user_id,
+        name,
+        req.title.as_deref(),
+        &req.content,
+        req.content_format.unwrap_or_default(),
+    )
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::CONFLICT, "Template already exists".to_string()))?;
+
+    Ok(Json(template))
+}
+
+pub async fn update_template(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(req): Json<TemplateRequest>,
+) -> Result<Json<Template>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+    let name = normalize_name(&req.name)?;
+
+    let template = db::update_template(
+        &state.db,
+        &id,
+        &user_id,
+        name,
+        req.title.as_deref(),
+        &req.content,
+        req.content_format.unwrap_or_default(),
+    )
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db_err) if db_err.is_unique_violation() => {
+            (StatusCode::CONFLICT, "Template already exists".to_string())
+        }
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    })?
+    .ok_or((StatusCode::NOT_FOUND, "Template not found".to_string()))?;
+
+    Ok(Json(template))
+}
+
+pub async fn delete_template(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    let deleted = db::delete_template(&state.db, &id, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if !deleted {
+        return Err((StatusCode::NOT_FOUND, "Template not found".to_string()));
+    }
+
+    Ok(Json(serde_json::json!({"message": "Template deleted"})))
+}
+
+/// テンプレートから下書きを作成するエンドポイント
+///
+/// 日記の日付はユーザーのタイムゾーンでのその日の始まりになる（日付省略時は現在日時）。
+/// 気分のラベルはユーザーが設定した表示ラベルを使う。
+/// テンプレートは平文のため、エンドツーエンド暗号化を有効にしている場合は使えない。
+pub async fn instantiate_template(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(req): Json<InstantiateTemplateRequest>,
+) -> Result<Json<Post>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    super::moods::validate_intensity(req.mood_intensity)?;
+    super::encryption::ensure_disabled(&state, &user_id, "Templates").await?;
+    if let Some(notebook_id) = req.notebook_id {
+        super::notebooks::ensure_notebook(&state, &notebook_id, &user_id).await?;
+    }
+
+    let template = db::get_template(&state.db, &id, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Template not found".to_string()))?;
+
+    let tz = super::settings::user_time_zone(&state, &user_id).await?;
+    let date = req.date.unwrap_or_else(|| timezone::today(tz));
+    let entry_date = req
+        .date
+        .map(|date| timezone::local_to_utc(tz, date.and_time(NaiveTime::MIN)));
+
+    let mood_label = match req.mood {
+        Some(mood) => {
+            let labels = db::get_mood_labels(&state.db, &user_id)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            Some(
+                labels
+                    .into_iter()
+                    .find(|(m, _)| m == mood.as_str())
+                    .map(|(_, label)| label)
+                    .unwrap_or_else(|| mood.default_label().to_string()),
+            )
+        }
+        None => None,
+    };
+
+    let title = template
+        .title
+        .as_deref()
+        .map(|title| fill_placeholders(title, date, mood_label.as_deref()));
+    let content = fill_placeholders(&template.content, date, mood_label.as_deref());
+
+    let post = db::create_post(
+        &state.db,
+        &user_id,
+        &db::NewPost {
+            title: title.as_deref(),
+            content: &content,
+            mood: req.mood.map(Mood::as_str),
+            mood_intensity: req.mood_intensity,
+            image_urls: &[],
+            audio_url: None,
+            content_format: ContentFormat::from_db(&template.content_format),
+            status: PostStatus::Draft,
+            entry_date,
+            notebook_id: req.notebook_id,
+            encryption: None,
+            tags: &[],
+        },
+    )
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(post))
+}
@@ -12,6 +12,7 @@ use crate::{
     auth::verify_token,
     db,
     models::{CreateTodoRequest, Todo, UpdateTodoRequest},
+    timezone,
     AppState,
 };
 
@@ -44,7 +45,10 @@ pub async fn list_todos(
 ) -> Result<Json<Vec<Todo>>, (StatusCode, String)> {
     let user_id = extract_user_id(&headers, &state.jwt_secret)?;
 
-    let date = params.target_date.unwrap_or_else(|| chrono::Local::now().date_naive());
+    let date = match params.target_date {
+        Some(date) => date,
+        None => timezone::today(super::settings::user_time_zone(&state, &user_id).await?),
+    };
 
     let todos = db::get_todos(&state.db, &user_id, date)
         .await
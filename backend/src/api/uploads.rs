@@ -1,19 +1,16 @@
 use axum::{
-    extract::{Multipart, State},
+    extract::{multipart::Field, Multipart, State},
     http::{header, StatusCode},
     Json,
 };
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 use std::sync::Arc;
 use uuid::Uuid;
 
-use crate::{auth::verify_token, AppState};
+use crate::{auth::verify_token, db, models::StorageUsage, AppState};
 
-pub async fn upload_image(
-    State(state): State<Arc<AppState>>,
-    headers: axum::http::HeaderMap,
-    mut multipart: Multipart,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    // Verify auth
+fn extract_user_id(headers: &axum::http::HeaderMap, jwt_secret: &str) -> Result<Uuid, (StatusCode, String)> {
     let auth_header = headers
         .get(header::AUTHORIZATION)
         .and_then(|h| h.to_str().ok())
@@ -23,60 +20,311 @@ pub async fn upload_image(
         .strip_prefix("Bearer ")
         .ok_or((StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
 
-    verify_token(token, &state.jwt_secret)
+    let claims = verify_token(token, jwt_secret)
         .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
 
-    // Process upload
-    while let Some(field) = multipart
+    Uuid::parse_str(&claims.sub)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid user id".to_string()))
+}
+
+/// ユーザーのストレージ上限（未設定ならサーバーの既定値）
+async fn storage_quota(state: &AppState, user_id: &Uuid) -> Result<i64, (StatusCode, String)> {
+    let quota = db::get_storage_quota(&state.db, user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(quota.unwrap_or(state.storage_quota_bytes))
+}
+
+/// 複数ファイルのアップロードでの1ファイルごとの結果
+#[derive(Debug, Serialize)]
+pub struct UploadResult {
+    pub filename: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stored_filename: Option<String>,
+    pub deduplicated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadImagesResponse {
+    pub results: Vec<UploadResult>,
+}
+
+/// アップロードできるファイルの種類
+#[derive(Debug, Clone, Copy)]
+enum MediaKind {
+    Image,
+    Audio,
+}
+
+impl MediaKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            MediaKind::Image => "image",
+            MediaKind::Audio => "audio",
+        }
+    }
+
+    fn content_type_prefix(self) -> &'static str {
+        match self {
+            MediaKind::Image => "image/",
+            MediaKind::Audio => "audio/",
+        }
+    }
+
+    fn max_bytes(self, state: &AppState) -> i64 {
+        match self {
+            MediaKind::Image => state.max_upload_bytes,
+            MediaKind::Audio => state.max_audio_upload_bytes,
+        }
+    }
+
+    /// 保存するファイルの拡張子をContent-Typeから決める（受け付けない形式は `None`）
+    fn extension(self, content_type: &str) -> Option<&'static str> {
+        let mime = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        let ext = match (self, mime.as_str()) {
+            (MediaKind::Image, "image/jpeg" | "image/jpg") => "jpg",
+            (MediaKind::Image, "image/png") => "png",
+            (MediaKind::Image, "image/gif") => "gif",
+            (MediaKind::Image, "image/webp") => "webp",
+            (MediaKind::Image, "image/heic") => "heic",
+            (MediaKind::Image, "image/heif") => "heif",
+            (MediaKind::Audio, "audio/webm") => "webm",
+            (MediaKind::Audio, "audio/ogg") => "ogg",
+            (MediaKind::Audio, "audio/mpeg" | "audio/mp3") => "mp3",
+            (MediaKind::Audio, "audio/mp4" | "audio/m4a" | "audio/x-m4a") => "m4a",
+            (MediaKind::Audio, "audio/wav" | "audio/x-wav" | "audio/wave") => "wav",
+            (MediaKind::Audio, "audio/aac") => "aac",
+            (MediaKind::Audio, "audio/flac") => "flac",
+            _ => return None,
+        };
+        Some(ext)
+    }
+}
+
+struct StoredFile {
+    url: String,
+    filename: String,
+    deduplicated: bool,
+}
+
+/// multipartの1フィールドを読み込んで保存する
+///
+/// 公開URLになるファイル名はランダムに付け、内容のハッシュは同じユーザーが同じ種類の
+/// ファイルを再びアップロードした場合の再利用にだけ使う（URLから内容を推測できないようにする）。
+/// ストレージ上限の確認と記録はファイルを書き込む前に1つのトランザクションで行い、
+/// 書き込みに失敗した場合は記録を取り消す。
+async fn store_file(
+    state: &AppState,
+    user_id: &Uuid,
+    field: &mut Field<'_>,
+    kind: MediaKind,
+) -> Result<StoredFile, (StatusCode, String)> {
+    let content_type = field
+        .content_type()
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+
+    // Validate content type
+    if !content_type.starts_with(kind.content_type_prefix()) {
+        let message = match kind {
+            MediaKind::Image => "Only image files are allowed",
+            MediaKind::Audio => "Only audio files are allowed",
+        };
+        return Err((StatusCode::BAD_REQUEST, message.to_string()));
+    }
+    let ext = kind
+        .extension(&content_type)
+        .ok_or((StatusCode::BAD_REQUEST, format!("Unsupported file type: {}", content_type)))?;
+
+    // 上限を超えるファイルは全体を読み込む前に拒否する
+    let max_bytes = kind.max_bytes(state);
+    let mut data = Vec::new();
+    let mut hasher = Sha256::new();
+    while let Some(chunk) = field
+        .chunk()
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+    {
+        if (data.len() + chunk.len()) as i64 > max_bytes {
+            return Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("File exceeds the maximum size of {} bytes", max_bytes),
+            ));
+        }
+        hasher.update(&chunk);
+        data.extend_from_slice(&chunk);
+    }
+    let size = data.len() as i64;
+    let content_hash = format!("{:x}", hasher.finalize());
+
+    // Generate unique filename
+    let new_filename = format!("{}.{}", Uuid::new_v4(), ext);
+
+    let new_upload = db::NewUpload {
+        media_kind: kind.as_str(),
+        filename: &new_filename,
+        content_type: &content_type,
+        size_bytes: size,
+        content_hash: &content_hash,
+    };
+    let record = db::create_upload(&state.db, user_id, &new_upload, state.storage_quota_bytes)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let upload = match record {
+        // 同じ内容をすでにアップロードしている場合は上限に数えずに再利用する
+        db::UploadRecord::Duplicate(existing) => {
+            return Ok(StoredFile {
+                url: format!("/uploads/{}", existing.filename),
+                filename: existing.filename,
+                deduplicated: true,
+            });
+        }
+        db::UploadRecord::QuotaExceeded { used, quota } => {
+            return Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("Storage quota exceeded ({} of {} bytes used)", used, quota),
+            ));
+        }
+        db::UploadRecord::Created(upload) => upload,
+    };
+
+    let path = format!("uploads/{}", new_filename);
+    if let Err(e) = tokio::fs::write(&path, &data).await {
+        db::delete_upload(&state.db, &upload.id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+    }
+
+    Ok(StoredFile {
+        url: format!("/uploads/{}", new_filename),
+        filename: new_filename,
+        deduplicated: false,
+    })
+}
+
+pub async fn upload_image(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    mut multipart: Multipart,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    while let Some(mut field) = multipart
         .next_field()
         .await
         .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
     {
-        let name = field.name().unwrap_or("").to_string();
-        if name != "file" {
+        if field.name() != Some("file") {
             continue;
         }
 
-        let filename = field
-            .file_name()
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| "upload".to_string());
+        let stored = store_file(&state, &user_id, &mut field, MediaKind::Image).await?;
 
-        let content_type = field
-            .content_type()
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| "application/octet-stream".to_string());
+        return Ok(Json(serde_json::json!({
+            "url": stored.url,
+            "filename": stored.filename
+        })));
+    }
+
+    Err((StatusCode::BAD_REQUEST, "No file provided".to_string()))
+}
 
-        // Validate content type
-        if !content_type.starts_with("image/") {
-            return Err((StatusCode::BAD_REQUEST, "Only image files are allowed".to_string()));
+/// 複数の `file` フィールドを受け付け、ファイルごとの結果かエラーを返す
+///
+/// 失敗したファイルがあっても他のファイルは保存する。
+pub async fn upload_images(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    mut multipart: Multipart,
+) -> Result<Json<UploadImagesResponse>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    let mut results = Vec::new();
+    while let Some(mut field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+    {
+        if field.name() != Some("file") {
+            continue;
         }
 
-        let data = field
-            .bytes()
-            .await
-            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-
-        // Generate unique filename
-        let ext = filename
-            .rsplit('.')
-            .next()
-            .unwrap_or("jpg");
-        let new_filename = format!("{}.{}", Uuid::new_v4(), ext);
-        let path = format!("uploads/{}", new_filename);
-
-        // Save file
-        tokio::fs::write(&path, &data)
-            .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        let filename = field.file_name().unwrap_or("upload").to_string();
+        let result = match store_file(&state, &user_id, &mut field, MediaKind::Image).await {
+            Ok(stored) => UploadResult {
+                filename,
+                url: Some(stored.url),
+                stored_filename: Some(stored.filename),
+                deduplicated: stored.deduplicated,
+                error: None,
+            },
+            Err((_, message)) => UploadResult {
+                filename,
+                url: None,
+                stored_filename: None,
+                deduplicated: false,
+                error: Some(message),
+            },
+        };
+        results.push(result);
+    }
+
+    if results.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "No file provided".to_string()));
+    }
+
+    Ok(Json(UploadImagesResponse { results }))
+}
+
+/// 音声メモを保存する（返したURLを `POST /api/v1/posts/voice` に渡すと投稿になる）
+pub async fn upload_audio(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    mut multipart: Multipart,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    while let Some(mut field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+    {
+        if field.name() != Some("file") {
+            continue;
+        }
 
-        let url = format!("/uploads/{}", new_filename);
+        let stored = store_file(&state, &user_id, &mut field, MediaKind::Audio).await?;
 
         return Ok(Json(serde_json::json!({
-            "url": url,
-            "filename": new_filename
+            "url": stored.url,
+            "filename": stored.filename
         })));
     }
 
     Err((StatusCode::BAD_REQUEST, "No file provided".to_string()))
 }
+
+pub async fn get_usage(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<StorageUsage>, (StatusCode, String)> {
+    let user_id = extract_user_id(&headers, &state.jwt_secret)?;
+
+    let quota = storage_quota(&state, &user_id).await?;
+    let used = db::get_storage_used(&state.db, &user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(StorageUsage {
+        used_bytes: used,
+        quota_bytes: quota,
+        remaining_bytes: (quota - used).max(0),
+        max_file_bytes: state.max_upload_bytes,
+    }))
+}
@@ -0,0 +1,113 @@
+//! 下書きの自動保存
+//!
+//! 自動保存の内容はメモリに溜めておき、一定間隔（`AUTOSAVE_FLUSH_SECS`）でまとめて書き込む。
+//! 同じ下書きへの頻繁な保存は、間隔ごとに1回の書き込みにまとまる。
+//! 終了時（SIGINT / SIGTERM）には残っている内容をすべて書き込んでから終了する。
+
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use crate::db;
+
+/// まだ書き込んでいない下書きの最新の内容
+pub struct PendingDraft {
+    user_id: Uuid,
+    title: Option<String>,
+    content: String,
+}
+
+/// 下書きの自動保存をメモリに溜めるバッファ
+#[derive(Default)]
+pub struct AutosaveBuffer {
+    pending: Mutex<HashMap<Uuid, PendingDraft>>,
+}
+
+impl AutosaveBuffer {
+    /// 下書きの内容を置き換える
+    pub fn stage(&self, post_id: Uuid, user_id: Uuid, title: Option<String>, content: String) {
+        self.pending
+            .lock()
+            .unwrap()
+            .insert(post_id, PendingDraft { user_id, title, content });
+    }
+
+    /// ユーザーのこの下書きの内容が溜まっているか
+    ///
+    /// 溜まっていれば、所有者と下書きであることは溜めた時点で確認済み。
+    pub fn is_pending(&self, post_id: &Uuid, user_id: &Uuid) -> bool {
+        self.pending
+            .lock()
+            .unwrap()
+            .get(post_id)
+            .is_some_and(|draft| draft.user_id == *user_id)
+    }
+
+    /// ユーザーのこの下書きの溜まっている `(タイトル, 本文)`
+    ///
+    /// 読み取りでは書き込まずに、保存済みの内容の代わりにこれを返す。
+    pub fn pending_content(&self, post_id: &Uuid, user_id: &Uuid) -> Option<(Option<String>, String)> {
+        self.pending
+            .lock()
+            .unwrap()
+            .get(post_id)
+            .filter(|draft| draft.user_id == *user_id)
+            .map(|draft| (draft.title.clone(), draft.content.clone()))
+    }
+
+    /// 溜まっている内容を捨てる（削除した投稿など）
+    pub fn discard(&self, post_id: &Uuid) {
+        self.pending.lock().unwrap().remove(post_id);
+    }
+
+    /// ユーザーのこの下書きの内容を取り出す
+    ///
+    /// 明示的な保存の間に自動保存が書き込まれないよう取り出しておき、
+    /// 保存に失敗した場合は `restore` で戻す。
+    pub fn take(&self, post_id: &Uuid, user_id: &Uuid) -> Option<PendingDraft> {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.get(post_id).is_some_and(|draft| draft.user_id == *user_id) {
+            pending.remove(post_id)
+        } else {
+            None
+        }
+    }
+
+    /// 取り出した内容を戻す（その間に新しい内容が溜まっていればそちらを残す）
+    pub fn restore(&self, post_id: Uuid, draft: PendingDraft) {
+        self.pending.lock().unwrap().entry(post_id).or_insert(draft);
+    }
+
+    /// 1件の下書きの溜まっている内容を書き込む
+    pub async fn flush_post(&self, pool: &PgPool, post_id: &Uuid) -> Result<(), sqlx::Error> {
+        let draft = self.pending.lock().unwrap().remove(post_id);
+        if let Some(draft) = draft {
+            self.write(pool, *post_id, draft).await?;
+        }
+        Ok(())
+    }
+
+    /// 溜まっている下書きをすべて書き込み、書き込んだ件数を返す
+    pub async fn flush(&self, pool: &PgPool) -> Result<usize, sqlx::Error> {
+        let drafts: Vec<_> = self.pending.lock().unwrap().drain().collect();
+        let count = drafts.len();
+
+        let mut result = Ok(count);
+        for (post_id, draft) in drafts {
+            if let Err(e) = self.write(pool, post_id, draft).await {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    async fn write(&self, pool: &PgPool, post_id: Uuid, draft: PendingDraft) -> Result<(), sqlx::Error> {
+        if let Err(e) = db::autosave_draft(pool, &post_id, &draft.user_id, draft.title.as_deref(), &draft.content).await {
+            // 次の書き込みで再試行する（その間に新しい内容が溜まっていればそちらを残す）
+            self.restore(post_id, draft);
+            return Err(e);
+        }
+        Ok(())
+    }
+}
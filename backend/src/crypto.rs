@@ -0,0 +1,433 @@
+//! 保存時の暗号化
+//!
+//! 投稿の本文・タイトル、リンク先のタイトル、分析結果をユーザーごとのデータ鍵（AES-256-GCM）で
+//! 暗号化してDBに保存する。データ鍵は設定のマスター鍵で包んで `user_data_keys` に保存する。
+//! 暗号化と復号は `db` モジュールで行うため、ハンドラーは平文だけを扱う。
+//! 暗号化した値は `enc:v1:` で始まる文字列で、暗号化したかどうかは値ではなく
+//! 行ごとの `encrypted_at_rest` に記録する（平文が `enc:v1:` で始まっていても誤って復号しない）。
+//! タイトルでのリンクの照合には、データ鍵から導いた鍵でのタイトルのHMAC（`title_hash`）を使う。
+//! - Keyring: マスター鍵（ローテーション中の旧鍵を含む）と復号したデータ鍵のキャッシュ
+//! - DataKey: ユーザーごとのデータ鍵での暗号化・復号
+//! - init / keyring: プロセス全体で使う鍵の設定
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use uuid::Uuid;
+
+/// 暗号化した値の接頭辞
+const PREFIX: &str = "enc:v1:";
+
+/// AES-GCMのnonceの長さ（バイト）
+const NONCE_LEN: usize = 12;
+
+/// データ鍵からタイトルのハッシュ用の鍵を導くときの用途ラベル
+const TITLE_HASH_LABEL: &[u8] = b"soulmap title hash v1";
+
+/// 復号したデータ鍵をキャッシュするユーザー数の上限
+const DATA_KEY_CACHE_CAPACITY: usize = 1024;
+
+static KEYRING: OnceLock<Keyring> = OnceLock::new();
+
+#[derive(Debug, thiserror::Error)]
+pub enum CryptoError {
+    #[error("Encrypted data found but ENCRYPTION_MASTER_KEY is not configured")]
+    NotConfigured,
+    #[error("Data key is wrapped with unknown master key {0}")]
+    UnknownMasterKey(String),
+    #[error("Malformed encrypted value")]
+    Malformed,
+    #[error("Decryption failed")]
+    DecryptionFailed,
+}
+
+impl From<CryptoError> for sqlx::Error {
+    fn from(e: CryptoError) -> Self {
+        sqlx::Error::Decode(Box::new(e))
+    }
+}
+
+/// nonce と暗号文を連結して返す
+fn seal(cipher: &Aes256Gcm, plaintext: &[u8]) -> Vec<u8> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .expect("AES-GCM encryption does not fail for in-memory buffers");
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    sealed
+}
+
+fn open(cipher: &Aes256Gcm, sealed: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    if sealed.len() < NONCE_LEN {
+        return Err(CryptoError::Malformed);
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| CryptoError::DecryptionFailed)
+}
+
+fn cipher(key: &[u8]) -> Aes256Gcm {
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+}
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().into()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// データ鍵を包むマスター鍵
+pub struct MasterKey {
+    /// 鍵のSHA-256の先頭8バイト（16進）。どの鍵で包んだかの記録に使う
+    id: String,
+    cipher: Aes256Gcm,
+}
+
+impl MasterKey {
+    /// base64でエンコードした32バイトの鍵から作る
+    pub fn from_base64(value: &str) -> anyhow::Result<Self> {
+        let bytes = STANDARD.decode(value.trim())?;
+        anyhow::ensure!(bytes.len() == 32, "Master key must be 32 bytes, got {}", bytes.len());
+        let id = hex(&Sha256::digest(&bytes)[..8]);
+        Ok(Self { id, cipher: cipher(&bytes) })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+/// ユーザーのデータ鍵
+///
+/// マスター鍵が設定されていない場合は `DataKey::plaintext()` を使い、値をそのまま保存する。
+#[derive(Clone)]
+pub struct DataKey(Option<SealingKey>);
+
+#[derive(Clone)]
+struct SealingKey {
+    cipher: Aes256Gcm,
+    /// タイトルのハッシュ用の鍵（データ鍵から導く）
+    title_hash_key: [u8; 32],
+}
+
+impl DataKey {
+    fn from_raw(key: &[u8]) -> Self {
+        Self(Some(SealingKey { cipher: cipher(key), title_hash_key: hmac_sha256(key, TITLE_HASH_LABEL) }))
+    }
+
+    /// 暗号化しない（保存時の暗号化が無効な）場合の鍵
+    pub fn plaintext() -> Self {
+        Self(None)
+    }
+
+    /// 暗号化して保存するか（行の `encrypted_at_rest` に記録する値）
+    pub fn is_sealing(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        match &self.0 {
+            Some(sealing) => format!("{}{}", PREFIX, STANDARD.encode(seal(&sealing.cipher, plaintext.as_bytes()))),
+            None => plaintext.to_string(),
+        }
+    }
+
+    /// 保存した値を読む（`sealed` は行の `encrypted_at_rest`。`false` なら平文としてそのまま返す）
+    pub fn decrypt(&self, value: &str, sealed: bool) -> Result<String, CryptoError> {
+        if !sealed {
+            return Ok(value.to_string());
+        }
+        let sealing = self.0.as_ref().ok_or(CryptoError::NotConfigured)?;
+        let encoded = value.strip_prefix(PREFIX).ok_or(CryptoError::Malformed)?;
+        let sealed = STANDARD.decode(encoded).map_err(|_| CryptoError::Malformed)?;
+        String::from_utf8(open(&sealing.cipher, &sealed)?).map_err(|_| CryptoError::Malformed)
+    }
+
+    /// リンクの照合に使うタイトルのハッシュ（前後の空白と大文字・小文字の違いは無視する）
+    ///
+    /// 同じ鍵なら同じタイトルは同じ値になるため、暗号化したタイトルの一致をDBで判定できる。
+    pub fn title_hash(&self, title: &str) -> String {
+        let title = title.trim().to_lowercase();
+        match &self.0 {
+            Some(sealing) => hex(&hmac_sha256(&sealing.title_hash_key, title.as_bytes())),
+            None => hex(&Sha256::digest(title.as_bytes())),
+        }
+    }
+
+    /// JSONを暗号化し、暗号文の文字列をJSONとして返す
+    pub fn encrypt_json(&self, value: &serde_json::Value) -> serde_json::Value {
+        match &self.0 {
+            Some(_) => serde_json::Value::String(self.encrypt(&value.to_string())),
+            None => value.clone(),
+        }
+    }
+
+    pub fn decrypt_json(&self, value: serde_json::Value, sealed: bool) -> Result<serde_json::Value, CryptoError> {
+        match value {
+            serde_json::Value::String(s) if sealed => {
+                serde_json::from_str(&self.decrypt(&s, true)?).map_err(|_| CryptoError::Malformed)
+            }
+            _ if sealed => Err(CryptoError::Malformed),
+            other => Ok(other),
+        }
+    }
+}
+
+/// 復号したデータ鍵のキャッシュ（上限を超えたら古く追加したものから捨てる）
+struct DataKeyCache {
+    capacity: usize,
+    keys: HashMap<Uuid, DataKey>,
+    order: VecDeque<Uuid>,
+}
+
+impl DataKeyCache {
+    fn new(capacity: usize) -> Self {
+        Self { capacity, keys: HashMap::new(), order: VecDeque::new() }
+    }
+
+    fn get(&self, user_id: &Uuid) -> Option<DataKey> {
+        self.keys.get(user_id).cloned()
+    }
+
+    fn insert(&mut self, user_id: Uuid, key: DataKey) {
+        if self.keys.insert(user_id, key).is_some() {
+            return;
+        }
+        self.order.push_back(user_id);
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.keys.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// マスター鍵と、復号したデータ鍵のキャッシュ
+pub struct Keyring {
+    /// 新しいデータ鍵を包む鍵（`None` なら保存時の暗号化は無効）
+    current: Option<MasterKey>,
+    /// ローテーション前の鍵（包み直すまでの復号用）
+    previous: Vec<MasterKey>,
+    data_keys: Mutex<DataKeyCache>,
+}
+
+impl Keyring {
+    pub fn new(current: Option<MasterKey>, previous: Vec<MasterKey>) -> Self {
+        Self {
+            current,
+            previous,
+            data_keys: Mutex::new(DataKeyCache::new(DATA_KEY_CACHE_CAPACITY)),
+        }
+    }
+
+    /// 環境変数から構築する
+    ///
+    /// `ENCRYPTION_MASTER_KEY` が現在のマスター鍵、`ENCRYPTION_PREVIOUS_MASTER_KEYS` が
+    /// ローテーション前の鍵（カンマ区切り）。いずれもbase64でエンコードした32バイトの鍵。
+    pub fn from_env() -> anyhow::Result<Self> {
+        let current = match std::env::var("ENCRYPTION_MASTER_KEY") {
+            Ok(value) if !value.trim().is_empty() => Some(MasterKey::from_base64(&value)?),
+            _ => None,
+        };
+        let previous = std::env::var("ENCRYPTION_PREVIOUS_MASTER_KEYS")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|value| !value.trim().is_empty())
+            .map(MasterKey::from_base64)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self::new(current, previous))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.current.is_some()
+    }
+
+    pub fn current_key_id(&self) -> Option<&str> {
+        self.current.as_ref().map(MasterKey::id)
+    }
+
+    fn master_key(&self, id: &str) -> Result<&MasterKey, CryptoError> {
+        self.current
+            .iter()
+            .chain(&self.previous)
+            .find(|key| key.id == id)
+            .ok_or_else(|| CryptoError::UnknownMasterKey(id.to_string()))
+    }
+
+    /// 新しいデータ鍵を作り、現在のマスター鍵で包んで `(鍵, 包んだ鍵, マスター鍵ID)` を返す
+    pub fn generate_data_key(&self) -> Result<(DataKey, String, String), CryptoError> {
+        let master = self.current.as_ref().ok_or(CryptoError::NotConfigured)?;
+        let key = Aes256Gcm::generate_key(OsRng);
+        let wrapped = STANDARD.encode(seal(&master.cipher, &key));
+        Ok((DataKey::from_raw(&key), wrapped, master.id.clone()))
+    }
+
+    fn unwrap_raw(&self, wrapped: &str, master_key_id: &str) -> Result<Vec<u8>, CryptoError> {
+        let master = self.master_key(master_key_id)?;
+        let sealed = STANDARD.decode(wrapped).map_err(|_| CryptoError::Malformed)?;
+        let key = open(&master.cipher, &sealed)?;
+        if key.len() != 32 {
+            return Err(CryptoError::Malformed);
+        }
+        Ok(key)
+    }
+
+    pub fn unwrap_data_key(&self, wrapped: &str, master_key_id: &str) -> Result<DataKey, CryptoError> {
+        Ok(DataKey::from_raw(&self.unwrap_raw(wrapped, master_key_id)?))
+    }
+
+    /// データ鍵を現在のマスター鍵で包み直す
+    pub fn rewrap_data_key(&self, wrapped: &str, master_key_id: &str) -> Result<String, CryptoError> {
+        let master = self.current.as_ref().ok_or(CryptoError::NotConfigured)?;
+        let key = self.unwrap_raw(wrapped, master_key_id)?;
+        Ok(STANDARD.encode(seal(&master.cipher, &key)))
+    }
+
+    pub fn cached_data_key(&self, user_id: &Uuid) -> Option<DataKey> {
+        self.data_keys.lock().unwrap().get(user_id)
+    }
+
+    pub fn cache_data_key(&self, user_id: Uuid, key: DataKey) {
+        self.data_keys.lock().unwrap().insert(user_id, key);
+    }
+}
+
+/// プロセス全体で使う鍵を設定する（起動時に一度だけ）
+///
+/// `db` の関数はプールしか受け取らないため、`AppState` ではなくここに置く。
+pub fn init(keyring: Keyring) {
+    if KEYRING.set(keyring).is_err() {
+        panic!("crypto::init called more than once");
+    }
+}
+
+/// 設定された鍵（`init` 前は保存時の暗号化が無効として扱う）
+pub fn keyring() -> &'static Keyring {
+    KEYRING.get_or_init(|| Keyring::new(None, Vec::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn master_key() -> MasterKey {
+        MasterKey::from_base64(&STANDARD.encode(Aes256Gcm::generate_key(OsRng))).unwrap()
+    }
+
+    fn data_key() -> DataKey {
+        Keyring::new(Some(master_key()), Vec::new()).generate_data_key().unwrap().0
+    }
+
+    #[test]
+    fn round_trips_text_and_json() {
+        let key = data_key();
+        let sealed = key.encrypt("今日の日記");
+        assert!(sealed.starts_with(PREFIX));
+        assert_ne!(key.encrypt("今日の日記"), sealed);
+        assert_eq!(key.decrypt(&sealed, true).unwrap(), "今日の日記");
+
+        let result = serde_json::json!({"summary": "穏やかな一日", "score": 0.5});
+        let sealed = key.encrypt_json(&result);
+        assert!(sealed.is_string());
+        assert_eq!(key.decrypt_json(sealed, true).unwrap(), result);
+    }
+
+    #[test]
+    fn unsealed_values_are_read_as_plaintext() {
+        let key = data_key();
+        assert_eq!(key.decrypt("enc:v1:not really", false).unwrap(), "enc:v1:not really");
+        let result = serde_json::json!("enc:v1:a string result");
+        assert_eq!(key.decrypt_json(result.clone(), false).unwrap(), result);
+
+        let plaintext = DataKey::plaintext();
+        assert!(!plaintext.is_sealing());
+        assert_eq!(plaintext.encrypt("text"), "text");
+        assert!(matches!(plaintext.decrypt(&data_key().encrypt("text"), true), Err(CryptoError::NotConfigured)));
+    }
+
+    #[test]
+    fn wrong_key_and_tampering_fail() {
+        let key = data_key();
+        let sealed = key.encrypt("secret");
+        assert!(matches!(data_key().decrypt(&sealed, true), Err(CryptoError::DecryptionFailed)));
+
+        let mut bytes = STANDARD.decode(sealed.strip_prefix(PREFIX).unwrap()).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        let tampered = format!("{}{}", PREFIX, STANDARD.encode(bytes));
+        assert!(matches!(key.decrypt(&tampered, true), Err(CryptoError::DecryptionFailed)));
+
+        assert!(matches!(key.decrypt("secret", true), Err(CryptoError::Malformed)));
+        assert!(matches!(key.decrypt("enc:v1:AAAA", true), Err(CryptoError::Malformed)));
+        assert!(matches!(key.decrypt_json(serde_json::json!({}), true), Err(CryptoError::Malformed)));
+    }
+
+    #[test]
+    fn title_hashes_ignore_case_and_depend_on_the_key() {
+        let key = data_key();
+        assert_eq!(key.title_hash(" Morning Pages "), key.title_hash("morning pages"));
+        assert_ne!(key.title_hash("morning pages"), key.title_hash("evening pages"));
+        assert_ne!(data_key().title_hash("morning pages"), key.title_hash("morning pages"));
+        assert_ne!(DataKey::plaintext().title_hash("morning pages"), key.title_hash("morning pages"));
+        assert_eq!(DataKey::plaintext().title_hash("Morning Pages"), DataKey::plaintext().title_hash("morning pages"));
+    }
+
+    #[test]
+    fn seal_and_open_check_the_nonce() {
+        let cipher = cipher(&Aes256Gcm::generate_key(OsRng));
+        let sealed = seal(&cipher, b"bytes");
+        assert_eq!(sealed.len(), NONCE_LEN + 5 + 16);
+        assert_eq!(open(&cipher, &sealed).unwrap(), b"bytes");
+        assert!(matches!(open(&cipher, &sealed[..NONCE_LEN - 1]), Err(CryptoError::Malformed)));
+    }
+
+    #[test]
+    fn rewrapped_data_keys_open_with_the_new_master_key() {
+        let old = Keyring::new(Some(master_key()), Vec::new());
+        let (key, wrapped, old_id) = old.generate_data_key().unwrap();
+        let sealed = key.encrypt("entry");
+
+        let new_master = master_key();
+        let new_id = new_master.id().to_string();
+        let rotated = Keyring::new(Some(new_master), vec![MasterKey { id: old_id.clone(), cipher: old.current.unwrap().cipher }]);
+        assert_eq!(rotated.unwrap_data_key(&wrapped, &old_id).unwrap().decrypt(&sealed, true).unwrap(), "entry");
+
+        let rewrapped = rotated.rewrap_data_key(&wrapped, &old_id).unwrap();
+        let only_new = Keyring::new(rotated.current, Vec::new());
+        assert_eq!(only_new.unwrap_data_key(&rewrapped, &new_id).unwrap().decrypt(&sealed, true).unwrap(), "entry");
+        assert!(matches!(only_new.unwrap_data_key(&wrapped, &old_id), Err(CryptoError::UnknownMasterKey(id)) if id == old_id));
+    }
+
+    #[test]
+    fn master_keys_must_be_32_bytes() {
+        assert!(MasterKey::from_base64(&STANDARD.encode([0u8; 16])).is_err());
+        assert!(MasterKey::from_base64("not base64!").is_err());
+        let key = MasterKey::from_base64(&STANDARD.encode([7u8; 32])).unwrap();
+        assert_eq!(key.id().len(), 16);
+    }
+
+    #[test]
+    fn data_key_cache_evicts_the_oldest_entries() {
+        let mut cache = DataKeyCache::new(2);
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        cache.insert(a, data_key());
+        cache.insert(b, data_key());
+        cache.insert(a, data_key());
+        cache.insert(c, data_key());
+        assert!(cache.get(&a).is_none());
+        assert!(cache.get(&b).is_some());
+        assert!(cache.get(&c).is_some());
+        assert_eq!(cache.keys.len(), 2);
+    }
+}
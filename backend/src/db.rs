@@ -1,7 +1,10 @@
-use chrono::NaiveDate;
-use sqlx::PgPool;
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::{PgConnection, PgPool};
+use std::collections::HashMap;
 use uuid::Uuid;
 
+use crate::crypto::{self, CryptoError, DataKey};
+use crate::links::{self, LinkTarget};
 use crate::models::*;
 
 // Users
@@ -33,148 +36,2526 @@ pub async fn get_user_by_id(pool: &PgPool, id: &Uuid) -> Result<Option<User>, sq
         .await
 }
 
+pub async fn get_user_preferences(pool: &PgPool, user_id: &Uuid) -> Result<Option<UserPreferences>, sqlx::Error> {
+    sqlx::query_as::<_, UserPreferences>("SELECT analyze_images, time_zone FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn update_user_preferences(pool: &PgPool, user_id: &Uuid, analyze_images: Option<bool>, time_zone: Option<&str>) -> Result<Option<UserPreferences>, sqlx::Error> {
+    sqlx::query_as::<_, UserPreferences>(
+        r#"UPDATE users SET
+           analyze_images = COALESCE($2, analyze_images),
+           time_zone = COALESCE($3, time_zone)
+           WHERE id = $1
+           RETURNING analyze_images, time_zone"#
+    )
+    .bind(user_id)
+    .bind(analyze_images)
+    .bind(time_zone)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn get_user_time_zone(pool: &PgPool, user_id: &Uuid) -> Result<Option<String>, sqlx::Error> {
+    let result: Option<(String,)> = sqlx::query_as("SELECT time_zone FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(result.map(|r| r.0))
+}
+
+// End-to-end encryption
+
+pub async fn get_encryption_key(pool: &PgPool, user_id: &Uuid) -> Result<Option<EncryptionKey>, sqlx::Error> {
+    sqlx::query_as::<_, EncryptionKey>(
+        r#"SELECT enabled, wrapped_key, wrap_algorithm, wrap_nonce, kdf, kdf_salt, kdf_params, created_at, updated_at
+           FROM user_encryption_keys WHERE user_id = $1"#
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// 包んだデータ鍵を登録して暗号化を有効にする（登録済みなら置き換える）
+pub async fn set_encryption_key(pool: &PgPool, user_id: &Uuid, key: &SetEncryptionKeyRequest) -> Result<EncryptionKey, sqlx::Error> {
+    sqlx::query_as::<_, EncryptionKey>(
+        r#"INSERT INTO user_encryption_keys (user_id, wrapped_key, wrap_algorithm, wrap_nonce, kdf, kdf_salt, kdf_params, created_at, updated_at)
+           VALUES ($1, $2, $3, $4, $5, $6, $7, NOW(), NOW())
+           ON CONFLICT (user_id) DO UPDATE SET
+               enabled = TRUE,
+               wrapped_key = EXCLUDED.wrapped_key,
+               wrap_algorithm = EXCLUDED.wrap_algorithm,
+               wrap_nonce = EXCLUDED.wrap_nonce,
+               kdf = EXCLUDED.kdf,
+               kdf_salt = EXCLUDED.kdf_salt,
+               kdf_params = EXCLUDED.kdf_params,
+               updated_at = NOW()
+           RETURNING enabled, wrapped_key, wrap_algorithm, wrap_nonce, kdf, kdf_salt, kdf_params, created_at, updated_at"#
+    )
+    .bind(user_id)
+    .bind(&key.wrapped_key)
+    .bind(&key.wrap_algorithm)
+    .bind(&key.wrap_nonce)
+    .bind(&key.kdf)
+    .bind(&key.kdf_salt)
+    .bind(&key.kdf_params)
+    .fetch_one(pool)
+    .await
+}
+
+/// 暗号化を無効にする
+///
+/// 暗号化された投稿がゴミ箱も含めて残っていれば復号用に鍵を残し、なければ削除する。
+pub async fn disable_encryption(pool: &PgPool, user_id: &Uuid) -> Result<Option<EncryptionKey>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"DELETE FROM user_encryption_keys
+           WHERE user_id = $1
+             AND NOT EXISTS (SELECT 1 FROM posts WHERE user_id = $1 AND encryption IS NOT NULL)"#
+    )
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    let key = sqlx::query_as::<_, EncryptionKey>(
+        r#"UPDATE user_encryption_keys SET enabled = FALSE, updated_at = NOW()
+           WHERE user_id = $1
+           RETURNING enabled, wrapped_key, wrap_algorithm, wrap_nonce, kdf, kdf_salt, kdf_params, created_at, updated_at"#
+    )
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(key)
+}
+
+// Encryption at rest
+
+/// ユーザーのデータ鍵（なければ作成する）
+///
+/// 保存時の暗号化が無効（マスター鍵が未設定）の場合は、値をそのまま保存する鍵を返す。
+async fn data_key(pool: &PgPool, user_id: &Uuid) -> Result<DataKey, sqlx::Error> {
+    let keyring = crypto::keyring();
+    if !keyring.is_enabled() {
+        return Ok(DataKey::plaintext());
+    }
+    if let Some(key) = keyring.cached_data_key(user_id) {
+        return Ok(key);
+    }
+
+    let query = "SELECT wrapped_key, master_key_id FROM user_data_keys WHERE user_id = $1";
+    let mut row: Option<(String, String)> = sqlx::query_as(query)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+    if row.is_none() {
+        let (_, wrapped_key, master_key_id) = keyring.generate_data_key()?;
+        // 同時に作成された場合は先に保存された鍵を使う
+        sqlx::query(
+            r#"INSERT INTO user_data_keys (user_id, wrapped_key, master_key_id, created_at, updated_at)
+               VALUES ($1, $2, $3, NOW(), NOW())
+               ON CONFLICT (user_id) DO NOTHING"#
+        )
+        .bind(user_id)
+        .bind(&wrapped_key)
+        .bind(&master_key_id)
+        .execute(pool)
+        .await?;
+        row = Some(sqlx::query_as(query).bind(user_id).fetch_one(pool).await?);
+    }
+
+    let (wrapped_key, master_key_id) = row.expect("data key row exists");
+    let key = keyring.unwrap_data_key(&wrapped_key, &master_key_id)?;
+    keyring.cache_data_key(*user_id, key.clone());
+    Ok(key)
+}
+
+/// 投稿の本文とタイトルを復号する（エンドツーエンド暗号化された投稿はクライアントの暗号文のまま）
+fn open_post(key: &DataKey, post: &mut Post) -> Result<(), sqlx::Error> {
+    if post.encryption.is_none() {
+        let sealed = post.encrypted_at_rest == Some(true);
+        post.content = key.decrypt(&post.content, sealed)?;
+        post.title = post.title.as_deref().map(|t| key.decrypt(t, sealed)).transpose()?;
+    }
+    Ok(())
+}
+
+fn open_posts(key: &DataKey, posts: &mut [Post]) -> Result<(), sqlx::Error> {
+    posts.iter_mut().try_for_each(|post| open_post(key, post))
+}
+
+fn open_revision(key: &DataKey, revision: &mut PostRevision) -> Result<(), sqlx::Error> {
+    if revision.encryption.is_none() {
+        let sealed = revision.encrypted_at_rest == Some(true);
+        revision.content = key.decrypt(&revision.content, sealed)?;
+        revision.title = revision.title.as_deref().map(|t| key.decrypt(t, sealed)).transpose()?;
+    }
+    Ok(())
+}
+
+fn open_analysis(key: &DataKey, analysis: &mut Analysis) -> Result<(), sqlx::Error> {
+    let sealed = analysis.encrypted_at_rest == Some(true);
+    analysis.result = key.decrypt_json(std::mem::take(&mut analysis.result), sealed)?;
+    Ok(())
+}
+
+/// 暗号化の有無を記録する前に保存された値（`sealed` が `None`）も読めるように復号する
+///
+/// 以前は接頭辞で判断していたため、接頭辞があり `key` で復号できる値だけを暗号文とみなす。
+fn open_legacy(key: &DataKey, value: &str, sealed: Option<bool>) -> Result<String, CryptoError> {
+    match sealed {
+        Some(sealed) => key.decrypt(value, sealed),
+        None => Ok(key.decrypt(value, true).unwrap_or_else(|_| value.to_string())),
+    }
+}
+
+fn open_legacy_json(key: &DataKey, value: serde_json::Value, sealed: Option<bool>) -> Result<serde_json::Value, CryptoError> {
+    match sealed {
+        Some(sealed) => key.decrypt_json(value, sealed),
+        None => Ok(key.decrypt_json(value.clone(), true).unwrap_or(value)),
+    }
+}
+
+/// ユーザーの保存しているデータを `old` で読んで `new` で暗号化し直し、書き換えた行数を返す
+///
+/// タイトルのハッシュも `new` で計算し直す。
+/// `unsealed_only` の場合は暗号化されていない行（記録する前の行を含む）だけを書き換える。
+async fn reseal_user_data(conn: &mut PgConnection, user_id: &Uuid, old: &DataKey, new: &DataKey, unsealed_only: bool) -> Result<u64, sqlx::Error> {
+    let reseal = |value: &str, sealed: Option<bool>| -> Result<String, CryptoError> { Ok(new.encrypt(&open_legacy(old, value, sealed)?)) };
+
+    let posts: Vec<(Uuid, Option<String>, String, Option<bool>)> = sqlx::query_as(
+        r#"SELECT id, title, content, encrypted_at_rest FROM posts
+           WHERE user_id = $1 AND encryption IS NULL AND NOT ($2 AND encrypted_at_rest IS TRUE)"#
+    )
+    .bind(user_id)
+    .bind(unsealed_only)
+    .fetch_all(&mut *conn)
+    .await?;
+    for (id, title, content, sealed) in &posts {
+        let title = title.as_deref().map(|t| open_legacy(old, t, *sealed)).transpose()?;
+        sqlx::query("UPDATE posts SET title = $2, content = $3, encrypted_at_rest = $4, title_hash = $5 WHERE id = $1")
+            .bind(id)
+            .bind(title.as_deref().map(|t| new.encrypt(t)))
+            .bind(reseal(content, *sealed)?)
+            .bind(new.is_sealing())
+            .bind(title.as_deref().map(|t| new.title_hash(t)))
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    let revisions: Vec<(Uuid, Option<String>, String, Option<bool>)> = sqlx::query_as(
+        r#"SELECT r.id, r.title, r.content, r.encrypted_at_rest FROM post_revisions r JOIN posts p ON p.id = r.post_id
+           WHERE p.user_id = $1 AND r.encryption IS NULL AND NOT ($2 AND r.encrypted_at_rest IS TRUE)"#
+    )
+    .bind(user_id)
+    .bind(unsealed_only)
+    .fetch_all(&mut *conn)
+    .await?;
+    for (id, title, content, sealed) in &revisions {
+        sqlx::query("UPDATE post_revisions SET title = $2, content = $3, encrypted_at_rest = $4 WHERE id = $1")
+            .bind(id)
+            .bind(title.as_deref().map(|t| reseal(t, *sealed)).transpose()?)
+            .bind(reseal(content, *sealed)?)
+            .bind(new.is_sealing())
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    let link_titles: Vec<(Uuid, String, Option<bool>)> = sqlx::query_as(
+        r#"SELECT l.id, l.target_title, l.encrypted_at_rest FROM post_links l JOIN posts p ON p.id = l.source_post_id
+           WHERE p.user_id = $1 AND l.target_title IS NOT NULL AND NOT ($2 AND l.encrypted_at_rest IS TRUE)"#
+    )
+    .bind(user_id)
+    .bind(unsealed_only)
+    .fetch_all(&mut *conn)
+    .await?;
+    for (id, target_title, sealed) in &link_titles {
+        let target_title = open_legacy(old, target_title, *sealed)?;
+        sqlx::query("UPDATE post_links SET target_title = $2, encrypted_at_rest = $3, target_title_hash = $4 WHERE id = $1")
+            .bind(id)
+            .bind(new.encrypt(&target_title))
+            .bind(new.is_sealing())
+            .bind(new.title_hash(&target_title))
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    let analyses: Vec<(Uuid, serde_json::Value, Option<bool>)> = sqlx::query_as(
+        "SELECT id, result, encrypted_at_rest FROM analyses WHERE user_id = $1 AND NOT ($2 AND encrypted_at_rest IS TRUE)"
+    )
+    .bind(user_id)
+    .bind(unsealed_only)
+    .fetch_all(&mut *conn)
+    .await?;
+    let analysis_count = analyses.len();
+    for (id, result, sealed) in analyses {
+        sqlx::query("UPDATE analyses SET result = $2, encrypted_at_rest = $3 WHERE id = $1")
+            .bind(id)
+            .bind(new.encrypt_json(&open_legacy_json(old, result, sealed)?))
+            .bind(new.is_sealing())
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    Ok((posts.len() + revisions.len() + link_titles.len() + analysis_count) as u64)
+}
+
+/// 起動時に、保存時の暗号化がされていない行を暗号化し、暗号化した行数を返す
+///
+/// マスター鍵を設定する前に保存された行と、暗号化の有無を記録する前の行が対象。
+/// 書き込みと重ならないよう、リクエストを受け付ける前に呼ぶ。
+/// マスター鍵が未設定の場合は何もしないが、データ鍵があれば暗号化したデータが読めないためエラーにする。
+pub async fn seal_existing_data(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    if !crypto::keyring().is_enabled() {
+        let (has_data_keys,): (bool,) = sqlx::query_as("SELECT EXISTS (SELECT 1 FROM user_data_keys)")
+            .fetch_one(pool)
+            .await?;
+        if has_data_keys {
+            return Err(CryptoError::NotConfigured.into());
+        }
+        return Ok(0);
+    }
+
+    let user_ids: Vec<(Uuid,)> = sqlx::query_as(
+        r#"SELECT user_id FROM posts WHERE encrypted_at_rest IS NOT TRUE AND encryption IS NULL
+           UNION SELECT user_id FROM post_revisions WHERE encrypted_at_rest IS NOT TRUE AND encryption IS NULL
+           UNION SELECT p.user_id FROM post_links l JOIN posts p ON p.id = l.source_post_id
+                 WHERE l.encrypted_at_rest IS NOT TRUE AND l.target_title IS NOT NULL
+           UNION SELECT user_id FROM analyses WHERE encrypted_at_rest IS NOT TRUE"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut sealed = 0;
+    for (user_id,) in user_ids {
+        let key = data_key(pool, &user_id).await?;
+        let mut tx = pool.begin().await?;
+        sealed += reseal_user_data(&mut tx, &user_id, &key, &key, true).await?;
+        tx.commit().await?;
+    }
+    Ok(sealed)
+}
+
+/// データ鍵のローテーションと稼働中のサーバーを排他するアドバイザリロックのキー
+const DATA_KEY_LOCK_ID: i64 = 0x736f_756c_6d61_7001;
+
+/// サーバーの稼働中に保持する共有ロックを取る（返した接続を閉じるまで保持される）
+///
+/// データ鍵のローテーション中はローテーションが終わるまで待つ。
+pub async fn lock_data_keys_shared(pool: &PgPool) -> Result<PgConnection, sqlx::Error> {
+    let mut conn = pool.acquire().await?.detach();
+    sqlx::query("SELECT pg_advisory_lock_shared($1)")
+        .bind(DATA_KEY_LOCK_ID)
+        .execute(&mut conn)
+        .await?;
+    Ok(conn)
+}
+
+/// データ鍵のローテーション用の排他ロックを取る（サーバーが稼働中なら `None`）
+pub async fn try_lock_data_keys(pool: &PgPool) -> Result<Option<PgConnection>, sqlx::Error> {
+    let mut conn = pool.acquire().await?.detach();
+    let (locked,): (bool,) = sqlx::query_as("SELECT pg_try_advisory_lock($1)")
+        .bind(DATA_KEY_LOCK_ID)
+        .fetch_one(&mut conn)
+        .await?;
+    Ok(locked.then_some(conn))
+}
+
+/// マスター鍵のローテーション: 古いマスター鍵で包まれたデータ鍵を現在のマスター鍵で包み直し、件数を返す
+pub async fn rewrap_data_keys(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let keyring = crypto::keyring();
+    let current = keyring.current_key_id().ok_or(CryptoError::NotConfigured)?;
+
+    let rows: Vec<(Uuid, String, String)> = sqlx::query_as(
+        "SELECT user_id, wrapped_key, master_key_id FROM user_data_keys WHERE master_key_id <> $1"
+    )
+    .bind(current)
+    .fetch_all(pool)
+    .await?;
+
+    let mut tx = pool.begin().await?;
+    for (user_id, wrapped_key, master_key_id) in &rows {
+        sqlx::query("UPDATE user_data_keys SET wrapped_key = $2, master_key_id = $3, updated_at = NOW() WHERE user_id = $1")
+            .bind(user_id)
+            .bind(keyring.rewrap_data_key(wrapped_key, master_key_id)?)
+            .bind(current)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(rows.len() as u64)
+}
+
+/// データ鍵のローテーション: ユーザーのデータ鍵を新しく作り、保存しているデータをすべて暗号化し直す
+///
+/// 稼働中のサーバーはキャッシュした古い鍵で書き込むため、`try_lock_data_keys` で
+/// サーバーが止まっていることを確かめてから呼ぶ。
+pub async fn rotate_data_key(pool: &PgPool, user_id: &Uuid) -> Result<(), sqlx::Error> {
+    let keyring = crypto::keyring();
+    let mut tx = pool.begin().await?;
+
+    let row: Option<(String, String)> = sqlx::query_as(
+        "SELECT wrapped_key, master_key_id FROM user_data_keys WHERE user_id = $1 FOR UPDATE"
+    )
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let old = match row {
+        Some((wrapped_key, master_key_id)) => keyring.unwrap_data_key(&wrapped_key, &master_key_id)?,
+        None => DataKey::plaintext(),
+    };
+    let (new, wrapped_key, master_key_id) = keyring.generate_data_key()?;
+    reseal_user_data(&mut tx, user_id, &old, &new, false).await?;
+
+    sqlx::query(
+        r#"INSERT INTO user_data_keys (user_id, wrapped_key, master_key_id, created_at, updated_at)
+           VALUES ($1, $2, $3, NOW(), NOW())
+           ON CONFLICT (user_id) DO UPDATE SET
+               wrapped_key = EXCLUDED.wrapped_key,
+               master_key_id = EXCLUDED.master_key_id,
+               updated_at = NOW()"#
+    )
+    .bind(user_id)
+    .bind(&wrapped_key)
+    .bind(&master_key_id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    keyring.cache_data_key(*user_id, new);
+    Ok(())
+}
+
+pub async fn get_user_ids(pool: &PgPool) -> Result<Vec<Uuid>, sqlx::Error> {
+    let rows: Vec<(Uuid,)> = sqlx::query_as("SELECT id FROM users ORDER BY created_at")
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().map(|r| r.0).collect())
+}
+
 // Posts
-pub async fn create_post(pool: &PgPool, user_id: &Uuid, title: Option<&str>, content: &str, mood: Option<&str>, image_urls: &[String]) -> Result<Post, sqlx::Error> {
-    sqlx::query_as::<_, Post>(
-        r#"INSERT INTO posts (id, user_id, title, content, mood, image_urls, created_at, updated_at)
+
+/// 投稿に紐づくタグ名の配列を返すSELECT句
+const POST_TAGS_COLUMN: &str = "COALESCE((SELECT array_agg(t.name ORDER BY t.name) FROM post_tags pt JOIN tags t ON t.id = pt.tag_id WHERE pt.post_id = posts.id), '{}') AS tags";
+
+/// 新規投稿の内容
+#[derive(Debug, Default)]
+pub struct NewPost<'a> {
+    pub title: Option<&'a str>,
+    pub content: &'a str,
+    pub mood: Option<&'a str>,
+    pub mood_intensity: Option<i16>,
+    pub image_urls: &'a [String],
+    pub audio_url: Option<&'a str>,
+    pub content_format: ContentFormat,
+    pub status: PostStatus,
+    pub entry_date: Option<DateTime<Utc>>,
+    pub notebook_id: Option<Uuid>,
+    /// エンドツーエンド暗号化された投稿の復号情報
+    pub encryption: Option<&'a serde_json::Value>,
+    /// 正規化済みのタグ名（存在しないタグは作成する）
+    pub tags: &'a [String],
+}
+
+pub async fn create_post(pool: &PgPool, user_id: &Uuid, post: &NewPost<'_>) -> Result<Post, sqlx::Error> {
+    // エンドツーエンド暗号化された投稿はクライアントの暗号文をそのまま保存する
+    let key = match post.encryption {
+        Some(_) => DataKey::plaintext(),
+        None => data_key(pool, user_id).await?,
+    };
+    let mut tx = pool.begin().await?;
+
+    let mut created = sqlx::query_as::<_, Post>(
+        r#"INSERT INTO posts (id, user_id, title, content, mood, mood_intensity, image_urls, audio_url, status, entry_date, notebook_id, content_format, encryption, encrypted_at_rest, created_at, updated_at)
+           VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, COALESCE($10, NOW()), $11, $12, $13, $14, NOW(), NOW())
+           RETURNING *"#
+    )
+    .bind(Uuid::new_v4())
+    .bind(user_id)
+    .bind(post.title.map(|t| key.encrypt(t)))
+    .bind(key.encrypt(post.content))
+    .bind(post.mood)
+    .bind(post.mood_intensity)
+    .bind(serde_json::json!(post.image_urls))
+    .bind(post.audio_url)
+    .bind(post.status.as_str())
+    .bind(post.entry_date)
+    .bind(post.notebook_id)
+    .bind(post.content_format.as_str())
+    .bind(post.encryption)
+    .bind(key.is_sealing())
+    .fetch_one(&mut *tx)
+    .await?;
+    open_post(&key, &mut created)?;
+    store_text_counts(&mut tx, &created).await?;
+    store_title_hash(&mut tx, &key, &created).await?;
+    if !post.tags.is_empty() {
+        created.tags = write_post_tags(&mut tx, &created.id, user_id, post.tags, false).await?;
+    }
+
+    // 下書きの履歴は公開時から記録する
+    if post.status == PostStatus::Published {
+        record_revision(&mut tx, &created.id).await?;
+    }
+    update_links(&mut tx, &key, &created).await?;
+
+    tx.commit().await?;
+    Ok(created)
+}
+
+/// 投稿一覧の絞り込み条件
+#[derive(Debug, Default)]
+pub struct PostFilters<'a> {
+    pub search: Option<&'a str>,
+    pub date_from: Option<DateTime<Utc>>,
+    pub date_to: Option<DateTime<Utc>>,
+    pub tag: Option<&'a str>,
+    pub mood: Option<&'a str>,
+    pub notebook: Option<Uuid>,
+    /// いずれかのノートの投稿に限定する（閲覧者に共有されたノートなど）
+    pub notebooks: Option<&'a [Uuid]>,
+    /// 既定では公開済みの投稿のみ
+    pub status: PostStatus,
+}
+
+/// 検索語の照合を復号した投稿で行うか
+///
+/// 保存時の暗号化が有効な場合は本文をDBで照合できないため、`matches_search` で照合する。
+/// 無効な場合はDBで照合する。
+fn searches_decrypted() -> bool {
+    crypto::keyring().is_enabled()
+}
+
+/// 検索語を部分一致で照合する `ILIKE` のパターン（$10）
+fn search_pattern(filters: &PostFilters<'_>) -> Option<String> {
+    let search = filters.search.filter(|_| !searches_decrypted())?;
+    let escaped = search.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    Some(format!("%{}%", escaped))
+}
+
+/// 絞り込み条件のWHERE句（$4〜$10 を使用する）
+///
+/// 検索語は、保存時の暗号化が有効な場合はここでは検索できる投稿に絞るだけで、
+/// 照合は復号後に `matches_search` で行う。
+fn post_filter_clause(filters: &PostFilters<'_>) -> String {
+    let mut clause = format!(" AND status = '{}'", filters.status.as_str());
+    if filters.search.is_some() {
+        clause.push_str(" AND encryption IS NULL");
+        if !searches_decrypted() {
+            clause.push_str(" AND (title ILIKE $10 OR content ILIKE $10)");
+        }
+    }
+    if filters.date_from.is_some() {
+        clause.push_str(" AND entry_date >= $4");
+    }
+    if filters.date_to.is_some() {
+        clause.push_str(" AND entry_date <= $5");
+    }
+    if filters.tag.is_some() {
+        clause.push_str(" AND EXISTS (SELECT 1 FROM post_tags pt JOIN tags t ON t.id = pt.tag_id WHERE pt.post_id = posts.id AND t.name = $6)");
+    }
+    if filters.mood.is_some() {
+        clause.push_str(" AND mood = $7");
+    }
+    if filters.notebook.is_some() {
+        clause.push_str(" AND notebook_id = $8");
+    }
+    if filters.notebooks.is_some() {
+        clause.push_str(" AND notebook_id = ANY($9)");
+    }
+    clause
+}
+
+/// 復号した投稿のタイトルか本文が検索語を含むか（大文字小文字を区別しない）
+fn matches_search(post: &Post, search: &str) -> bool {
+    let search = search.to_lowercase();
+    post.content.to_lowercase().contains(&search)
+        || post.title.as_deref().is_some_and(|t| t.to_lowercase().contains(&search))
+}
+
+/// 絞り込み条件に一致する投稿を新しい順に取得して復号する
+///
+/// 検索語はDBで照合できる場合のみ条件に含める（`searches_decrypted`）。
+/// `after` より古い投稿に限定できる。
+async fn query_posts(pool: &PgPool, user_id: &Uuid, limit: i32, offset: i32, after: Option<(DateTime<Utc>, Uuid)>, filters: &PostFilters<'_>) -> Result<Vec<Post>, sqlx::Error> {
+    let PostFilters { date_from, date_to, tag, mood, notebook, notebooks, .. } = *filters;
+
+    let mut query = format!("SELECT posts.*, {} FROM posts WHERE user_id = $1 AND deleted_at IS NULL", POST_TAGS_COLUMN);
+    query.push_str(&post_filter_clause(filters));
+    if after.is_some() {
+        query.push_str(" AND (entry_date, id) < ($11, $12)");
+    }
+    query.push_str(" ORDER BY entry_date DESC, id DESC LIMIT $2 OFFSET $3");
+
+    let mut posts = sqlx::query_as::<_, Post>(&query)
+        .bind(user_id)
+        .bind(limit)
+        .bind(offset)
+        .bind(date_from)
+        .bind(date_to)
+        .bind(tag)
+        .bind(mood)
+        .bind(notebook)
+        .bind(notebooks)
+        .bind(search_pattern(filters))
+        .bind(after.map(|(entry_date, _)| entry_date))
+        .bind(after.map(|(_, id)| id))
+        .fetch_all(pool)
+        .await?;
+
+    open_posts(&data_key(pool, user_id).await?, &mut posts)?;
+    Ok(posts)
+}
+
+pub async fn get_posts(pool: &PgPool, user_id: &Uuid, page: i32, per_page: i32, filters: &PostFilters<'_>) -> Result<(Vec<Post>, i64), sqlx::Error> {
+    let offset = (page - 1).saturating_mul(per_page);
+
+    if let Some(search) = filters.search.filter(|_| searches_decrypted()) {
+        return search_posts_page(pool, user_id, per_page, offset, filters, search).await;
+    }
+
+    let PostFilters { date_from, date_to, tag, mood, notebook, notebooks, .. } = *filters;
+    let posts = query_posts(pool, user_id, per_page, offset, None, filters).await?;
+
+    // Bind the same parameters as the page query so placeholder numbers line up
+    let count_query = format!(
+        "SELECT COUNT(*) FROM posts WHERE user_id = $1 AND deleted_at IS NULL{}",
+        post_filter_clause(filters)
+    );
+    let total: (i64,) = sqlx::query_as(&count_query)
+        .bind(user_id)
+        .bind(per_page)
+        .bind(offset)
+        .bind(date_from)
+        .bind(date_to)
+        .bind(tag)
+        .bind(mood)
+        .bind(notebook)
+        .bind(notebooks)
+        .bind(search_pattern(filters))
+        .fetch_one(pool)
+        .await?;
+
+    Ok((posts, total.0))
+}
+
+/// 検索で一度に読み込んで照合する投稿数
+const SEARCH_BATCH_SIZE: i32 = 200;
+
+/// 復号して照合する検索で、一致した投稿のうち `offset` 件目から最大 `limit` 件と一致した総数を返す
+///
+/// 総数を数えるために一致しうる投稿はすべて照合するが、少しずつ読み込み、ページに入る投稿だけを残す。
+async fn search_posts_page(pool: &PgPool, user_id: &Uuid, limit: i32, offset: i32, filters: &PostFilters<'_>, search: &str) -> Result<(Vec<Post>, i64), sqlx::Error> {
+    let (limit, offset) = (limit.max(0) as usize, offset.max(0) as usize);
+    let mut page = Vec::new();
+    let mut total = 0;
+    let mut after = None;
+
+    loop {
+        let batch = query_posts(pool, user_id, SEARCH_BATCH_SIZE, 0, after, filters).await?;
+        let exhausted = batch.len() < SEARCH_BATCH_SIZE as usize;
+        after = batch.last().map(|post| (post.entry_date, post.id));
+        for post in batch.into_iter().filter(|post| matches_search(post, search)) {
+            if total >= offset && page.len() < limit {
+                page.push(post);
+            }
+            total += 1;
+        }
+        if exhausted {
+            return Ok((page, total as i64));
+        }
+    }
+}
+
+/// 復号して照合する検索で、一致する投稿を `after` より古いものから新しい順に最大 `limit` 件返す
+///
+/// 少しずつ読み込み、`limit` 件集まった時点で読み込みをやめる。
+async fn search_posts_after(pool: &PgPool, user_id: &Uuid, limit: i32, mut after: Option<(DateTime<Utc>, Uuid)>, filters: &PostFilters<'_>, search: &str) -> Result<Vec<Post>, sqlx::Error> {
+    let limit = limit.max(0) as usize;
+    let mut matched = Vec::new();
+
+    while matched.len() < limit {
+        let batch = query_posts(pool, user_id, SEARCH_BATCH_SIZE, 0, after, filters).await?;
+        let exhausted = batch.len() < SEARCH_BATCH_SIZE as usize;
+        after = batch.last().map(|post| (post.entry_date, post.id));
+        matched.extend(batch.into_iter().filter(|post| matches_search(post, search)));
+        if exhausted {
+            break;
+        }
+    }
+
+    matched.truncate(limit);
+    Ok(matched)
+}
+
+/// `(entry_date, id)` によるキーセットページネーション
+///
+/// `after` より古い投稿を新しい順に最大 `limit` 件返す。件数は数えない。
+pub async fn get_posts_after(pool: &PgPool, user_id: &Uuid, limit: i32, after: Option<(DateTime<Utc>, Uuid)>, filters: &PostFilters<'_>) -> Result<Vec<Post>, sqlx::Error> {
+    match filters.search.filter(|_| searches_decrypted()) {
+        Some(search) => search_posts_after(pool, user_id, limit, after, filters, search).await,
+        None => query_posts(pool, user_id, limit, 0, after, filters).await,
+    }
+}
+
+pub async fn get_post_by_id(pool: &PgPool, id: &Uuid, user_id: &Uuid) -> Result<Option<Post>, sqlx::Error> {
+    let query = format!("SELECT posts.*, {} FROM posts WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL", POST_TAGS_COLUMN);
+    let Some(mut post) = sqlx::query_as::<_, Post>(&query)
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?
+    else {
+        return Ok(None);
+    };
+    open_post(&data_key(pool, user_id).await?, &mut post)?;
+    Ok(Some(post))
+}
+
+/// 投稿の変更内容（`None` の項目は変更しない）
+#[derive(Debug, Default)]
+pub struct PostChanges<'a> {
+    pub title: Option<&'a str>,
+    pub content: Option<&'a str>,
+    pub mood: Option<&'a str>,
+    pub mood_intensity: Option<i16>,
+    pub image_urls: Option<&'a [String]>,
+    pub content_format: Option<ContentFormat>,
+    pub entry_date: Option<DateTime<Utc>>,
+    /// ノートを変える（`Some(None)` でノートから外す）
+    pub notebook_id: Option<Option<Uuid>>,
+    /// 本文の暗号化の状態を変える（`Some(None)` で平文に戻す）
+    ///
+    /// 暗号化された本文に置き換えるか暗号化された投稿を平文に戻す場合は、タイトルも `title` で置き換える。
+    pub encryption: Option<Option<&'a serde_json::Value>>,
+    /// 正規化済みのタグ名で置き換える（存在しないタグは作成する）
+    pub tags: Option<&'a [String]>,
+}
+
+/// `expected_version` が指定された場合、現在のバージョンと一致するときのみ更新する
+pub async fn update_post(pool: &PgPool, id: &Uuid, user_id: &Uuid, changes: &PostChanges<'_>, expected_version: Option<i32>) -> Result<Option<Post>, sqlx::Error> {
+    let image_urls_json = changes.image_urls.map(|urls| serde_json::json!(urls));
+
+    let query = format!(
+        r#"UPDATE posts SET
+           title = CASE WHEN $12 AND ($13::jsonb IS NOT NULL OR encryption IS NOT NULL) THEN $3 ELSE COALESCE($3, title) END,
+           content = COALESCE($4, content),
+           encryption = CASE WHEN $12 THEN $13 ELSE encryption END,
+           encrypted_at_rest = (CASE WHEN $12 THEN $13 ELSE encryption END) IS NULL AND $15,
+           mood = COALESCE($5, mood),
+           mood_intensity = COALESCE($6, mood_intensity),
+           image_urls = COALESCE($7, image_urls),
+           entry_date = COALESCE($9, entry_date),
+           notebook_id = CASE WHEN $10 THEN $14 ELSE notebook_id END,
+           content_format = COALESCE($11, content_format),
+           version = version + 1,
+           updated_at = NOW()
+           WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL
+             AND ($8::int IS NULL OR version = $8)
+           RETURNING posts.*, {}"#,
+        POST_TAGS_COLUMN
+    );
+    let key = data_key(pool, user_id).await?;
+    // エンドツーエンド暗号化された本文とタイトルはクライアントの暗号文をそのまま保存する
+    let seal_key = match changes.encryption {
+        Some(Some(_)) => DataKey::plaintext(),
+        _ => key.clone(),
+    };
+    let mut tx = pool.begin().await?;
+
+    let mut post = sqlx::query_as::<_, Post>(&query)
+        .bind(id)
+        .bind(user_id)
+        .bind(changes.title.map(|t| seal_key.encrypt(t)))
+        .bind(changes.content.map(|c| seal_key.encrypt(c)))
+        .bind(changes.mood)
+        .bind(changes.mood_intensity)
+        .bind(image_urls_json)
+        .bind(expected_version)
+        .bind(changes.entry_date)
+        .bind(changes.notebook_id.is_some())
+        .bind(changes.content_format.map(ContentFormat::as_str))
+        .bind(changes.encryption.is_some())
+        .bind(changes.encryption.flatten())
+        .bind(changes.notebook_id.flatten())
+        .bind(key.is_sealing())
+        .fetch_optional(&mut *tx)
+        .await?;
+
+    if let Some(post) = &mut post {
+        open_post(&key, post)?;
+        // 暗号化した投稿の平文の履歴と、本文の内容を含む分析結果は残さない
+        if post.encryption.is_some() {
+            sqlx::query("DELETE FROM post_revisions WHERE post_id = $1 AND encryption IS NULL")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM analyses WHERE post_id = $1")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        store_text_counts(&mut tx, post).await?;
+        store_title_hash(&mut tx, &key, post).await?;
+        if let Some(tags) = changes.tags {
+            post.tags = write_post_tags(&mut tx, id, user_id, tags, true).await?;
+        }
+        record_revision(&mut tx, id).await?;
+        update_links(&mut tx, &key, post).await?;
+    }
+
+    tx.commit().await?;
+    Ok(post)
+}
+
+/// 絞り込み条件に一致する投稿のIDを新しい順に最大 `limit` 件返す
+///
+/// 検索語を復号して照合する場合以外はIDだけを読み込む。
+pub async fn get_matching_post_ids(pool: &PgPool, user_id: &Uuid, limit: i32, filters: &PostFilters<'_>) -> Result<Vec<Uuid>, sqlx::Error> {
+    if let Some(search) = filters.search.filter(|_| searches_decrypted()) {
+        let posts = search_posts_after(pool, user_id, limit, None, filters, search).await?;
+        return Ok(posts.into_iter().map(|post| post.id).collect());
+    }
+
+    let PostFilters { date_from, date_to, tag, mood, notebook, notebooks, .. } = *filters;
+    let query = format!(
+        "SELECT id FROM posts WHERE user_id = $1 AND deleted_at IS NULL{} ORDER BY entry_date DESC, id DESC LIMIT $2 OFFSET $3",
+        post_filter_clause(filters)
+    );
+    let rows: Vec<(Uuid,)> = sqlx::query_as(&query)
+        .bind(user_id)
+        .bind(limit)
+        .bind(0)
+        .bind(date_from)
+        .bind(date_to)
+        .bind(tag)
+        .bind(mood)
+        .bind(notebook)
+        .bind(notebooks)
+        .bind(search_pattern(filters))
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().map(|r| r.0).collect())
+}
+
+/// 複数の投稿に対する変更
+#[derive(Debug)]
+pub enum BulkChange<'a> {
+    Delete,
+    Move(Option<Uuid>),
+    Tag(&'a [String]),
+    Untag(&'a [String]),
+}
+
+/// 複数の投稿を1つのトランザクションで変更し、変更できた投稿のIDを返す
+///
+/// 存在しない・他のユーザーの投稿は無視される。途中で失敗した場合は何も変更しない。
+/// 移動・タグ付け・タグ解除で実際に変わった投稿はバージョンが上がる。
+pub async fn bulk_update_posts(pool: &PgPool, user_id: &Uuid, ids: &[Uuid], change: &BulkChange<'_>) -> Result<Vec<Uuid>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let rows: Vec<(Uuid,)> = sqlx::query_as(
+        "SELECT id FROM posts WHERE id = ANY($1) AND user_id = $2 AND deleted_at IS NULL ORDER BY id FOR UPDATE"
+    )
+    .bind(ids)
+    .bind(user_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    let found: Vec<Uuid> = rows.into_iter().map(|r| r.0).collect();
+
+    match change {
+        BulkChange::Delete => {
+            sqlx::query("UPDATE posts SET deleted_at = NOW() WHERE id = ANY($1)")
+                .bind(&found)
+                .execute(&mut *tx)
+                .await?;
+            unlink_trashed_posts(&mut tx, user_id, &found).await?;
+        }
+        BulkChange::Move(notebook_id) => {
+            sqlx::query(
+                r#"UPDATE posts SET notebook_id = $2, version = version + 1, updated_at = NOW()
+                   WHERE id = ANY($1) AND notebook_id IS DISTINCT FROM $2"#
+            )
+            .bind(&found)
+            .bind(notebook_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        BulkChange::Tag(names) => {
+            sqlx::query(
+                r#"INSERT INTO tags (id, user_id, name, created_at)
+                   SELECT gen_random_uuid(), $1, name, NOW() FROM UNNEST($2::text[]) AS name
+                   ON CONFLICT (user_id, name) DO NOTHING"#
+            )
+            .bind(user_id)
+            .bind(names)
+            .execute(&mut *tx)
+            .await?;
+
+            // タグが増えた投稿だけバージョンを上げる
+            sqlx::query(
+                r#"WITH added AS (
+                       INSERT INTO post_tags (post_id, tag_id)
+                       SELECT p.id, t.id FROM UNNEST($1::uuid[]) AS p(id)
+                       CROSS JOIN tags t WHERE t.user_id = $2 AND t.name = ANY($3)
+                       ON CONFLICT DO NOTHING
+                       RETURNING post_id
+                   )
+                   UPDATE posts SET version = version + 1, updated_at = NOW()
+                   WHERE id IN (SELECT post_id FROM added)"#
+            )
+            .bind(&found)
+            .bind(user_id)
+            .bind(names)
+            .execute(&mut *tx)
+            .await?;
+        }
+        BulkChange::Untag(names) => {
+            sqlx::query(
+                r#"WITH removed AS (
+                       DELETE FROM post_tags WHERE post_id = ANY($1)
+                       AND tag_id IN (SELECT id FROM tags WHERE user_id = $2 AND name = ANY($3))
+                       RETURNING post_id
+                   )
+                   UPDATE posts SET version = version + 1, updated_at = NOW()
+                   WHERE id IN (SELECT post_id FROM removed)"#
+            )
+            .bind(&found)
+            .bind(user_id)
+            .bind(names)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    tx.commit().await?;
+    Ok(found)
+}
+
+/// 自動保存された下書きの内容を書き込む（履歴やバージョンは更新しない）
+///
+/// 自動保存は平文のみのため、暗号化された下書きは平文に戻る。
+pub async fn autosave_draft(pool: &PgPool, id: &Uuid, user_id: &Uuid, title: Option<&str>, content: &str) -> Result<bool, sqlx::Error> {
+    let key = data_key(pool, user_id).await?;
+    let result = sqlx::query(
+        r#"UPDATE posts SET title = $3, content = $4, encryption = NULL, encrypted_at_rest = $7, title_hash = $8, word_count = $5, character_count = $6, updated_at = NOW()
+           WHERE id = $1 AND user_id = $2 AND status = 'draft' AND deleted_at IS NULL"#
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(title.map(|t| key.encrypt(t)))
+    .bind(key.encrypt(content))
+    .bind(count_words(content) as i32)
+    .bind(count_characters(content) as i32)
+    .bind(key.is_sealing())
+    .bind(title.map(|t| key.title_hash(t)))
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// 下書きを公開する（投稿日時は公開した時点になり、記録日は下書きのまま残る）
+pub async fn publish_post(pool: &PgPool, id: &Uuid, user_id: &Uuid) -> Result<Option<Post>, sqlx::Error> {
+    let query = format!(
+        r#"UPDATE posts SET status = 'published', version = version + 1, created_at = NOW(), updated_at = NOW()
+           WHERE id = $1 AND user_id = $2 AND status = 'draft' AND deleted_at IS NULL
+           RETURNING posts.*, {}"#,
+        POST_TAGS_COLUMN
+    );
+    let key = data_key(pool, user_id).await?;
+    let mut tx = pool.begin().await?;
+
+    let mut post = sqlx::query_as::<_, Post>(&query)
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+    // 自動保存では更新していないリンクを公開時に反映する
+    if let Some(post) = &mut post {
+        open_post(&key, post)?;
+        record_revision(&mut tx, id).await?;
+        update_links(&mut tx, &key, post).await?;
+    }
+
+    tx.commit().await?;
+    Ok(post)
+}
+
+/// 投稿をゴミ箱に移動する（分析結果や履歴は完全削除まで残る）
+pub async fn delete_post(pool: &PgPool, id: &Uuid, user_id: &Uuid) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query("UPDATE posts SET deleted_at = NOW() WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL")
+        .bind(id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    unlink_trashed_posts(&mut tx, user_id, &[*id]).await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+// Trash
+pub async fn get_deleted_posts(pool: &PgPool, user_id: &Uuid) -> Result<Vec<Post>, sqlx::Error> {
+    let query = format!("SELECT posts.*, {} FROM posts WHERE user_id = $1 AND deleted_at IS NOT NULL ORDER BY deleted_at DESC", POST_TAGS_COLUMN);
+    let mut posts = sqlx::query_as::<_, Post>(&query)
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+    open_posts(&data_key(pool, user_id).await?, &mut posts)?;
+    Ok(posts)
+}
+
+pub async fn restore_post(pool: &PgPool, id: &Uuid, user_id: &Uuid) -> Result<Option<Post>, sqlx::Error> {
+    let query = format!(
+        "UPDATE posts SET deleted_at = NULL WHERE id = $1 AND user_id = $2 AND deleted_at IS NOT NULL RETURNING posts.*, {}",
+        POST_TAGS_COLUMN
+    );
+    let key = data_key(pool, user_id).await?;
+    let mut tx = pool.begin().await?;
+    let Some(mut post) = sqlx::query_as::<_, Post>(&query)
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?
+    else {
+        return Ok(None);
+    };
+    open_post(&key, &mut post)?;
+    // ゴミ箱にある間に未解決になったリンクを戻す
+    link_dangling(&mut tx, &key, &post).await?;
+    tx.commit().await?;
+    Ok(Some(post))
+}
+
+/// ゴミ箱内の投稿を完全に削除する（`id` が `None` の場合はゴミ箱を空にする）
+pub async fn purge_posts(pool: &PgPool, user_id: &Uuid, id: Option<&Uuid>) -> Result<u64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let ids: Vec<(Uuid,)> = sqlx::query_as("SELECT id FROM posts WHERE user_id = $1 AND deleted_at IS NOT NULL AND ($2::uuid IS NULL OR id = $2) FOR UPDATE")
+        .bind(user_id)
+        .bind(id)
+        .fetch_all(&mut *tx)
+        .await?;
+    let purged = purge(&mut tx, &ids.into_iter().map(|r| r.0).collect::<Vec<_>>()).await?;
+    tx.commit().await?;
+    Ok(purged)
+}
+
+/// 保持期間を過ぎたゴミ箱内の投稿を全ユーザー分完全に削除する
+pub async fn purge_expired_posts(pool: &PgPool, retention_days: i32) -> Result<u64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let ids: Vec<(Uuid,)> = sqlx::query_as("SELECT id FROM posts WHERE deleted_at IS NOT NULL AND deleted_at < NOW() - make_interval(days => $1) FOR UPDATE")
+        .bind(retention_days)
+        .fetch_all(&mut *tx)
+        .await?;
+    let purged = purge(&mut tx, &ids.into_iter().map(|r| r.0).collect::<Vec<_>>()).await?;
+    tx.commit().await?;
+    Ok(purged)
+}
+
+/// 投稿を削除する
+///
+/// IDで書かれたリンクはリンク先がなくなると意味を持たないため一緒に削除する。
+/// タイトルで書かれたリンクは未解決として残り、同じタイトルの投稿ができれば再びつながる。
+async fn purge(conn: &mut PgConnection, ids: &[Uuid]) -> Result<u64, sqlx::Error> {
+    sqlx::query("DELETE FROM post_links WHERE target_post_id = ANY($1) AND target_title IS NULL")
+        .bind(ids)
+        .execute(&mut *conn)
+        .await?;
+    let result = sqlx::query("DELETE FROM posts WHERE id = ANY($1)")
+        .bind(ids)
+        .execute(&mut *conn)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+// Revisions
+
+/// 投稿の現在の内容を新しいリビジョンとして保存する
+async fn record_revision(conn: &mut PgConnection, post_id: &Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"INSERT INTO post_revisions (id, post_id, user_id, revision_number, title, content, mood, mood_intensity, image_urls, encryption, encrypted_at_rest, created_at)
+           SELECT $1, p.id, p.user_id,
+                  COALESCE((SELECT MAX(revision_number) FROM post_revisions WHERE post_id = p.id), 0) + 1,
+                  p.title, p.content, p.mood, p.mood_intensity, p.image_urls, p.encryption, p.encrypted_at_rest, NOW()
+           FROM posts p WHERE p.id = $2"#
+    )
+    .bind(Uuid::new_v4())
+    .bind(post_id)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// ゴミ箱の投稿の履歴は返さない
+pub async fn get_post_revisions(pool: &PgPool, post_id: &Uuid, user_id: &Uuid) -> Result<Vec<PostRevision>, sqlx::Error> {
+    let mut revisions = sqlx::query_as::<_, PostRevision>(
+        r#"SELECT r.* FROM post_revisions r
+           JOIN posts p ON p.id = r.post_id AND p.deleted_at IS NULL
+           WHERE r.post_id = $1 AND r.user_id = $2
+           ORDER BY r.revision_number DESC"#
+    )
+    .bind(post_id)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    let key = data_key(pool, user_id).await?;
+    revisions.iter_mut().try_for_each(|revision| open_revision(&key, revision))?;
+    Ok(revisions)
+}
+
+pub async fn get_post_revision(pool: &PgPool, post_id: &Uuid, user_id: &Uuid, revision_number: i32) -> Result<Option<PostRevision>, sqlx::Error> {
+    let Some(mut revision) = sqlx::query_as::<_, PostRevision>(
+        r#"SELECT r.* FROM post_revisions r
+           JOIN posts p ON p.id = r.post_id AND p.deleted_at IS NULL
+           WHERE r.post_id = $1 AND r.user_id = $2 AND r.revision_number = $3"#
+    )
+    .bind(post_id)
+    .bind(user_id)
+    .bind(revision_number)
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    open_revision(&data_key(pool, user_id).await?, &mut revision)?;
+    Ok(Some(revision))
+}
+
+pub async fn get_latest_revision_id(pool: &PgPool, post_id: &Uuid) -> Result<Option<Uuid>, sqlx::Error> {
+    let result: Option<(Uuid,)> = sqlx::query_as("SELECT id FROM post_revisions WHERE post_id = $1 ORDER BY revision_number DESC LIMIT 1")
+        .bind(post_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(result.map(|r| r.0))
+}
+
+/// 指定したリビジョンの内容に投稿を戻す（復元も新しいリビジョンとして記録する）
+///
+/// `expected_version` が指定された場合、現在のバージョンと一致するときのみ戻す。
+pub async fn restore_post_revision(pool: &PgPool, post_id: &Uuid, user_id: &Uuid, revision_number: i32, expected_version: Option<i32>) -> Result<Option<Post>, sqlx::Error> {
+    let query = format!(
+        r#"UPDATE posts SET
+           title = r.title,
+           content = r.content,
+           mood = r.mood,
+           mood_intensity = r.mood_intensity,
+           image_urls = r.image_urls,
+           encryption = r.encryption,
+           encrypted_at_rest = r.encrypted_at_rest,
+           version = posts.version + 1,
+           updated_at = NOW()
+           FROM post_revisions r
+           WHERE posts.id = $1 AND posts.user_id = $2 AND posts.deleted_at IS NULL
+             AND r.post_id = posts.id AND r.revision_number = $3
+             AND ($4::int IS NULL OR posts.version = $4)
+           RETURNING posts.*, {}"#,
+        POST_TAGS_COLUMN
+    );
+
+    let key = data_key(pool, user_id).await?;
+    let mut tx = pool.begin().await?;
+
+    let mut post = sqlx::query_as::<_, Post>(&query)
+        .bind(post_id)
+        .bind(user_id)
+        .bind(revision_number)
+        .bind(expected_version)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+    if let Some(post) = &mut post {
+        open_post(&key, post)?;
+        store_text_counts(&mut tx, post).await?;
+        store_title_hash(&mut tx, &key, post).await?;
+        record_revision(&mut tx, post_id).await?;
+        update_links(&mut tx, &key, post).await?;
+    }
+
+    tx.commit().await?;
+    Ok(post)
+}
+
+// Links
+
+/// タイトルのハッシュごとに、そのタイトルを持つゴミ箱にない最新の投稿を返す
+///
+/// タイトルは暗号化して保存しているため、`DataKey::title_hash` の値で照合する。
+async fn find_titled_posts(conn: &mut PgConnection, user_id: &Uuid, exclude_id: &Uuid, title_hashes: &[String]) -> Result<HashMap<String, Uuid>, sqlx::Error> {
+    let rows: Vec<(String, Uuid)> = sqlx::query_as(
+        r#"SELECT DISTINCT ON (title_hash) title_hash, id FROM posts
+           WHERE user_id = $1 AND id <> $2 AND title_hash = ANY($3)
+             AND deleted_at IS NULL AND encryption IS NULL
+           ORDER BY title_hash, entry_date DESC"#
+    )
+    .bind(user_id)
+    .bind(exclude_id)
+    .bind(title_hashes)
+    .fetch_all(conn)
+    .await?;
+    Ok(rows.into_iter().collect())
+}
+
+/// 投稿のタイトルのハッシュを保存する（エンドツーエンド暗号化された投稿はタイトルで照合しない）
+async fn store_title_hash(conn: &mut PgConnection, key: &DataKey, post: &Post) -> Result<(), sqlx::Error> {
+    let title_hash = post
+        .title
+        .as_deref()
+        .filter(|_| post.encryption.is_none())
+        .map(|t| key.title_hash(t));
+    sqlx::query("UPDATE posts SET title_hash = $2 WHERE id = $1")
+        .bind(post.id)
+        .bind(title_hash)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// タイトルのハッシュが未計算の投稿とリンク（追加前からある行）を埋め、件数を返す
+///
+/// 起動時に `seal_existing_data` の後で呼ぶ。
+pub async fn backfill_title_hashes(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let user_ids: Vec<(Uuid,)> = sqlx::query_as(
+        r#"SELECT user_id FROM posts WHERE title_hash IS NULL AND title IS NOT NULL AND encryption IS NULL
+           UNION SELECT p.user_id FROM post_links l JOIN posts p ON p.id = l.source_post_id
+                 WHERE l.target_title_hash IS NULL AND l.target_title IS NOT NULL"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut filled = 0;
+    for (user_id,) in user_ids {
+        let key = data_key(pool, &user_id).await?;
+        let mut tx = pool.begin().await?;
+
+        let posts: Vec<(Uuid, String, bool)> = sqlx::query_as(
+            r#"SELECT id, title, encrypted_at_rest IS TRUE FROM posts
+               WHERE user_id = $1 AND title_hash IS NULL AND title IS NOT NULL AND encryption IS NULL"#
+        )
+        .bind(user_id)
+        .fetch_all(&mut *tx)
+        .await?;
+        for (id, title, sealed) in &posts {
+            sqlx::query("UPDATE posts SET title_hash = $2 WHERE id = $1")
+                .bind(id)
+                .bind(key.title_hash(&key.decrypt(title, *sealed)?))
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let links: Vec<(Uuid, String, bool)> = sqlx::query_as(
+            r#"SELECT l.id, l.target_title, l.encrypted_at_rest IS TRUE FROM post_links l JOIN posts p ON p.id = l.source_post_id
+               WHERE p.user_id = $1 AND l.target_title_hash IS NULL AND l.target_title IS NOT NULL"#
+        )
+        .bind(user_id)
+        .fetch_all(&mut *tx)
+        .await?;
+        for (id, target_title, sealed) in &links {
+            sqlx::query("UPDATE post_links SET target_title_hash = $2 WHERE id = $1")
+                .bind(id)
+                .bind(key.title_hash(&key.decrypt(target_title, *sealed)?))
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        filled += (posts.len() + links.len()) as u64;
+    }
+    Ok(filled)
+}
+
+/// 保存された投稿に合わせてリンク表を更新する
+///
+/// `post` は復号したもの。リンク先のタイトルは暗号化し、照合用のハッシュと一緒に保存する。
+/// - 本文中のリンクを張り直す（他のユーザーの投稿へのリンクは無視する）
+/// - タイトルで書かれたリンクは、解決済みならリンク先の名前が変わっても同じ投稿に向け続ける
+///   （リンク元の本文は書き換えない）
+/// - 未解決のリンクのうち、この投稿のタイトルに一致するものをこの投稿に向ける
+async fn update_links(conn: &mut PgConnection, key: &DataKey, post: &Post) -> Result<(), sqlx::Error> {
+    let mut ids = Vec::new();
+    let mut titles = Vec::new();
+    for target in links::parse(&post.content) {
+        match target {
+            LinkTarget::Id(id) => ids.push(id),
+            LinkTarget::Title(title) => titles.push(title),
+        }
+    }
+
+    let resolved: Vec<(String, Uuid)> = sqlx::query_as(
+        r#"SELECT l.target_title_hash, l.target_post_id
+           FROM post_links l JOIN posts t ON t.id = l.target_post_id AND t.deleted_at IS NULL
+           WHERE l.source_post_id = $1 AND l.target_title_hash IS NOT NULL"#
+    )
+    .bind(post.id)
+    .fetch_all(&mut *conn)
+    .await?;
+    let resolved: HashMap<String, Uuid> = resolved.into_iter().collect();
+
+    sqlx::query("DELETE FROM post_links WHERE source_post_id = $1")
+        .bind(post.id)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query(
+        r#"INSERT INTO post_links (id, source_post_id, target_post_id, target_title, created_at)
+           SELECT gen_random_uuid(), $1, p.id, NULL, NOW()
+           FROM posts p
+           WHERE p.id = ANY($2) AND p.user_id = $3 AND p.id <> $1"#
+    )
+    .bind(post.id)
+    .bind(&ids)
+    .bind(post.user_id)
+    .execute(&mut *conn)
+    .await?;
+
+    if !titles.is_empty() {
+        let title_hashes: Vec<String> = titles.iter().map(|t| key.title_hash(t)).collect();
+        let candidates = find_titled_posts(conn, &post.user_id, &post.id, &title_hashes).await?;
+        let targets: Vec<Option<Uuid>> = title_hashes
+            .iter()
+            .map(|hash| resolved.get(hash).or_else(|| candidates.get(hash)).copied())
+            .collect();
+        let sealed_titles: Vec<String> = titles.iter().map(|t| key.encrypt(t)).collect();
+
+        sqlx::query(
+            r#"INSERT INTO post_links (id, source_post_id, target_post_id, target_title, target_title_hash, encrypted_at_rest, created_at)
+               SELECT gen_random_uuid(), $1, t.target_id, t.title, t.title_hash, $5, NOW()
+               FROM UNNEST($2::uuid[], $3::text[], $4::text[]) AS t(target_id, title, title_hash)"#
+        )
+        .bind(post.id)
+        .bind(&targets)
+        .bind(&sealed_titles)
+        .bind(&title_hashes)
+        .bind(key.is_sealing())
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    link_dangling(conn, key, post).await
+}
+
+/// 未解決のタイトルのリンクのうち、`post` のタイトルに一致するものを `post` に向ける
+///
+/// 暗号化されたタイトルではリンクしない。
+async fn link_dangling(conn: &mut PgConnection, key: &DataKey, post: &Post) -> Result<(), sqlx::Error> {
+    let Some(title) = post
+        .title
+        .as_deref()
+        .filter(|_| post.encryption.is_none())
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+    else {
+        return Ok(());
+    };
+
+    sqlx::query(
+        r#"UPDATE post_links l SET target_post_id = $1
+           FROM posts s
+           WHERE s.id = l.source_post_id AND s.user_id = $2 AND s.id <> $1
+             AND l.target_post_id IS NULL AND l.target_title_hash = $3"#
+    )
+    .bind(post.id)
+    .bind(post.user_id)
+    .bind(key.title_hash(title))
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// ゴミ箱に移動した投稿へのタイトルのリンクを、同じタイトルの別の投稿に向け直す（なければ未解決にする）
+///
+/// IDで書かれたリンクはそのまま残すため、元に戻せばリンクも戻る。
+async fn unlink_trashed_posts(conn: &mut PgConnection, user_id: &Uuid, ids: &[Uuid]) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"UPDATE post_links l SET target_post_id = (
+               SELECT p.id FROM posts p
+               WHERE p.user_id = $2 AND p.title_hash = l.target_title_hash AND p.id <> l.source_post_id
+                 AND p.deleted_at IS NULL AND p.encryption IS NULL
+               ORDER BY p.entry_date DESC LIMIT 1
+           )
+           WHERE l.target_post_id = ANY($1) AND l.target_title IS NOT NULL"#
+    )
+    .bind(ids)
+    .bind(user_id)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// 投稿へのリンクを持つ投稿（ゴミ箱・下書きを除く）
+///
+/// `notebooks` を指定するとそれらのノートの投稿のみを返す。
+pub async fn get_backlinks(pool: &PgPool, post_id: &Uuid, user_id: &Uuid, notebooks: Option<&[Uuid]>) -> Result<Vec<Backlink>, sqlx::Error> {
+    let mut backlinks = sqlx::query_as::<_, Backlink>(
+        r#"SELECT DISTINCT p.id, p.title, p.encrypted_at_rest, p.entry_date
+           FROM post_links l JOIN posts p ON p.id = l.source_post_id
+           WHERE l.target_post_id = $1 AND p.user_id = $2
+             AND p.deleted_at IS NULL AND p.status = 'published'
+             AND ($3::uuid[] IS NULL OR p.notebook_id = ANY($3))
+           ORDER BY p.entry_date DESC"#
+    )
+    .bind(post_id)
+    .bind(user_id)
+    .bind(notebooks)
+    .fetch_all(pool)
+    .await?;
+
+    let key = data_key(pool, user_id).await?;
+    for backlink in &mut backlinks {
+        let sealed = backlink.encrypted_at_rest == Some(true);
+        backlink.title = backlink.title.as_deref().map(|t| key.decrypt(t, sealed)).transpose()?;
+    }
+    Ok(backlinks)
+}
+
+// Tags
+pub async fn get_tags_with_counts(pool: &PgPool, user_id: &Uuid) -> Result<Vec<TagWithCount>, sqlx::Error> {
+    sqlx::query_as::<_, TagWithCount>(
+        r#"SELECT t.id, t.name, COUNT(p.id) AS post_count
+           FROM tags t
+           LEFT JOIN post_tags pt ON pt.tag_id = t.id
+           LEFT JOIN posts p ON p.id = pt.post_id AND p.deleted_at IS NULL AND p.status = 'published'
+           WHERE t.user_id = $1
+           GROUP BY t.id, t.name
+           ORDER BY post_count DESC, t.name"#
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// タグを作成する（同じ名前のタグがすでにあれば `None`）
+pub async fn create_tag(pool: &PgPool, user_id: &Uuid, name: &str) -> Result<Option<Tag>, sqlx::Error> {
+    sqlx::query_as::<_, Tag>(
+        r#"INSERT INTO tags (id, user_id, name, created_at)
+           VALUES ($1, $2, $3, NOW())
+           ON CONFLICT (user_id, name) DO NOTHING
+           RETURNING *"#
+    )
+    .bind(Uuid::new_v4())
+    .bind(user_id)
+    .bind(name)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn rename_tag(pool: &PgPool, id: &Uuid, user_id: &Uuid, name: &str) -> Result<Option<Tag>, sqlx::Error> {
+    sqlx::query_as::<_, Tag>("UPDATE tags SET name = $3 WHERE id = $1 AND user_id = $2 RETURNING *")
+        .bind(id)
+        .bind(user_id)
+        .bind(name)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn delete_tag(pool: &PgPool, id: &Uuid, user_id: &Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM tags WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// 投稿にタグを追加する（存在しないタグは作成する）
+///
+/// 既存のタグは残す。タグが増えた場合は投稿のバージョンを上げる。追加後の投稿のタグ名一覧を返す。
+pub async fn add_post_tags(pool: &PgPool, post_id: &Uuid, user_id: &Uuid, names: &[String]) -> Result<Vec<String>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT 1 FROM posts WHERE id = $1 AND user_id = $2 FOR UPDATE")
+        .bind(post_id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    let before: Vec<(String,)> = sqlx::query_as(
+        r#"SELECT t.name FROM posts p
+           JOIN post_tags pt ON pt.post_id = p.id JOIN tags t ON t.id = pt.tag_id
+           WHERE p.id = $1 AND p.user_id = $2 ORDER BY t.name"#
+    )
+    .bind(post_id)
+    .bind(user_id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let tags = write_post_tags(&mut tx, post_id, user_id, names, false).await?;
+    if before.into_iter().map(|t| t.0).ne(tags.iter().cloned()) {
+        sqlx::query("UPDATE posts SET version = version + 1, updated_at = NOW() WHERE id = $1")
+            .bind(post_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(tags)
+}
+
+async fn write_post_tags(conn: &mut PgConnection, post_id: &Uuid, user_id: &Uuid, names: &[String], replace: bool) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query(
+        r#"INSERT INTO tags (id, user_id, name, created_at)
+           SELECT gen_random_uuid(), $1, name, NOW() FROM UNNEST($2::text[]) AS name
+           ON CONFLICT (user_id, name) DO NOTHING"#
+    )
+    .bind(user_id)
+    .bind(names)
+    .execute(&mut *conn)
+    .await?;
+
+    if replace {
+        sqlx::query("DELETE FROM post_tags WHERE post_id = $1")
+            .bind(post_id)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    sqlx::query(
+        r#"INSERT INTO post_tags (post_id, tag_id)
+           SELECT $1, id FROM tags WHERE user_id = $2 AND name = ANY($3)
+           ON CONFLICT DO NOTHING"#
+    )
+    .bind(post_id)
+    .bind(user_id)
+    .bind(names)
+    .execute(&mut *conn)
+    .await?;
+
+    let tags: Vec<(String,)> = sqlx::query_as(
+        r#"SELECT t.name FROM post_tags pt JOIN tags t ON t.id = pt.tag_id
+           WHERE pt.post_id = $1 ORDER BY t.name"#
+    )
+    .bind(post_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(tags.into_iter().map(|t| t.0).collect())
+}
+
+// Notebooks
+pub async fn get_notebooks_with_counts(pool: &PgPool, user_id: &Uuid) -> Result<Vec<NotebookWithCount>, sqlx::Error> {
+    sqlx::query_as::<_, NotebookWithCount>(
+        r#"SELECT n.id, n.name, n.description, COUNT(p.id) AS post_count
+           FROM notebooks n
+           LEFT JOIN posts p ON p.notebook_id = n.id AND p.deleted_at IS NULL AND p.status = 'published'
+           WHERE n.user_id = $1
+           GROUP BY n.id, n.name, n.description
+           ORDER BY n.name"#
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn get_notebook(pool: &PgPool, id: &Uuid, user_id: &Uuid) -> Result<Option<Notebook>, sqlx::Error> {
+    sqlx::query_as::<_, Notebook>("SELECT * FROM notebooks WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+}
+
+/// ノートを作成する
+///
+/// ノート名はユーザーごとに一意で、同じ名前のノートがある場合は作成せず `None` を返す。
+pub async fn create_notebook(pool: &PgPool, user_id: &Uuid, name: &str, description: Option<&str>) -> Result<Option<Notebook>, sqlx::Error> {
+    sqlx::query_as::<_, Notebook>(
+        r#"INSERT INTO notebooks (id, user_id, name, description, created_at)
+           VALUES ($1, $2, $3, $4, NOW())
+           ON CONFLICT (user_id, name) DO NOTHING
+           RETURNING *"#
+    )
+    .bind(Uuid::new_v4())
+    .bind(user_id)
+    .bind(name)
+    .bind(description)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn update_notebook(pool: &PgPool, id: &Uuid, user_id: &Uuid, name: &str, description: Option<&str>) -> Result<Option<Notebook>, sqlx::Error> {
+    sqlx::query_as::<_, Notebook>("UPDATE notebooks SET name = $3, description = $4 WHERE id = $1 AND user_id = $2 RETURNING *")
+        .bind(id)
+        .bind(user_id)
+        .bind(name)
+        .bind(description)
+        .fetch_optional(pool)
+        .await
+}
+
+/// ノートを削除する（投稿はノートから外れて残る）
+pub async fn delete_notebook(pool: &PgPool, id: &Uuid, user_id: &Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM notebooks WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// Templates
+pub async fn get_templates(pool: &PgPool, user_id: &Uuid) -> Result<Vec<Template>, sqlx::Error> {
+    sqlx::query_as::<_, Template>("SELECT * FROM templates WHERE user_id = $1 ORDER BY name")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn get_template(pool: &PgPool, id: &Uuid, user_id: &Uuid) -> Result<Option<Template>, sqlx::Error> {
+    sqlx::query_as::<_, Template>("SELECT * FROM templates WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+}
+
+/// テンプレートを作成する（名前が重複する場合は `None`）
+pub async fn create_template(pool: &PgPool, user_id: &Uuid, name: &str, title: Option<&str>, content: &str, content_format: ContentFormat) -> Result<Option<Template>, sqlx::Error> {
+    sqlx::query_as::<_, Template>(
+        r#"INSERT INTO templates (id, user_id, name, title, content, content_format, created_at, updated_at)
            VALUES ($1, $2, $3, $4, $5, $6, NOW(), NOW())
+           ON CONFLICT (user_id, name) DO NOTHING
            RETURNING *"#
     )
-    .bind(Uuid::new_v4())
-    .bind(user_id)
-    .bind(title)
-    .bind(content)
-    .bind(mood)
-    .bind(serde_json::json!(image_urls))
-    .fetch_one(pool)
+    .bind(Uuid::new_v4())
+    .bind(user_id)
+    .bind(name)
+    .bind(title)
+    .bind(content)
+    .bind(content_format.as_str())
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn update_template(pool: &PgPool, id: &Uuid, user_id: &Uuid, name: &str, title: Option<&str>, content: &str, content_format: ContentFormat) -> Result<Option<Template>, sqlx::Error> {
+    sqlx::query_as::<_, Template>(
+        r#"UPDATE templates SET name = $3, title = $4, content = $5, content_format = $6, updated_at = NOW()
+           WHERE id = $1 AND user_id = $2
+           RETURNING *"#
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(name)
+    .bind(title)
+    .bind(content)
+    .bind(content_format.as_str())
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn delete_template(pool: &PgPool, id: &Uuid, user_id: &Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM templates WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// Share links
+
+/// 共有リンクのSELECT句（`s` は share_links）
+const SHARE_LINK_COLUMNS: &str = r#"s.id, s.post_id, s.password_hash IS NOT NULL AS has_password, s.include_analysis,
+    s.expires_at, s.revoked_at, s.created_at,
+    (SELECT COUNT(*) FROM share_link_views v WHERE v.share_link_id = s.id AND v.granted) AS view_count,
+    (SELECT MAX(v.viewed_at) FROM share_link_views v WHERE v.share_link_id = s.id AND v.granted) AS last_viewed_at"#;
+
+pub async fn create_share_link(pool: &PgPool, post_id: &Uuid, user_id: &Uuid, token_hash: &str, password_hash: Option<&str>, include_analysis: bool, expires_at: DateTime<Utc>) -> Result<ShareLink, sqlx::Error> {
+    let query = format!(
+        r#"WITH s AS (
+               INSERT INTO share_links (id, post_id, user_id, token_hash, password_hash, include_analysis, expires_at, created_at)
+               VALUES ($1, $2, $3, $4, $5, $6, $7, NOW())
+               RETURNING *
+           )
+           SELECT {} FROM s"#,
+        SHARE_LINK_COLUMNS
+    );
+    sqlx::query_as::<_, ShareLink>(&query)
+        .bind(Uuid::new_v4())
+        .bind(post_id)
+        .bind(user_id)
+        .bind(token_hash)
+        .bind(password_hash)
+        .bind(include_analysis)
+        .bind(expires_at)
+        .fetch_one(pool)
+        .await
+}
+
+pub async fn get_share_links(pool: &PgPool, post_id: &Uuid, user_id: &Uuid) -> Result<Vec<ShareLink>, sqlx::Error> {
+    let query = format!(
+        "SELECT {} FROM share_links s WHERE s.post_id = $1 AND s.user_id = $2 ORDER BY s.created_at DESC",
+        SHARE_LINK_COLUMNS
+    );
+    sqlx::query_as::<_, ShareLink>(&query)
+        .bind(post_id)
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn get_share_link(pool: &PgPool, id: &Uuid, user_id: &Uuid) -> Result<Option<ShareLink>, sqlx::Error> {
+    let query = format!("SELECT {} FROM share_links s WHERE s.id = $1 AND s.user_id = $2", SHARE_LINK_COLUMNS);
+    sqlx::query_as::<_, ShareLink>(&query)
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+}
+
+/// 共有リンクを無効にする（閲覧記録は残る）
+pub async fn revoke_share_link(pool: &PgPool, id: &Uuid, user_id: &Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("UPDATE share_links SET revoked_at = NOW() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// 有効期限内で無効化されていない共有リンクをトークンのハッシュで探す
+pub async fn find_active_share_link(pool: &PgPool, token_hash: &str) -> Result<Option<ShareAccess>, sqlx::Error> {
+    sqlx::query_as::<_, ShareAccess>(
+        r#"SELECT id, post_id, user_id, password_hash, include_analysis FROM share_links
+           WHERE token_hash = $1 AND revoked_at IS NULL AND expires_at > NOW()"#
+    )
+    .bind(token_hash)
+    .fetch_optional(pool)
     .await
 }
 
-pub async fn get_posts(pool: &PgPool, user_id: &Uuid, page: i32, per_page: i32, search: Option<&str>, date_from: Option<&str>, date_to: Option<&str>) -> Result<(Vec<Post>, i64), sqlx::Error> {
-    let offset = (page - 1) * per_page;
+pub async fn record_share_view(pool: &PgPool, share_link_id: &Uuid, granted: bool, ip_address: Option<&str>, user_agent: Option<&str>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"INSERT INTO share_link_views (id, share_link_id, granted, ip_address, user_agent, viewed_at)
+           VALUES ($1, $2, $3, $4, $5, NOW())"#
+    )
+    .bind(Uuid::new_v4())
+    .bind(share_link_id)
+    .bind(granted)
+    .bind(ip_address)
+    .bind(user_agent)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// パスワード付きの共有リンクへの試行を失敗として記録し、記録のIDを返す
+///
+/// 直近 `window_minutes` 分の失敗が `max_failures` 件に達していれば記録せずに `None` を返す。
+/// 同時の試行が上限を超えないよう、共有リンクの行をロックしてから数える。
+/// パスワードが一致した場合は `grant_share_view` で成功に変える。
+pub async fn begin_share_attempt(pool: &PgPool, share_link_id: &Uuid, max_failures: i64, window_minutes: i32, ip_address: Option<&str>, user_agent: Option<&str>) -> Result<Option<Uuid>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
 
-    let mut query = String::from("SELECT * FROM posts WHERE user_id = $1");
-    let mut count_query = String::from("SELECT COUNT(*) FROM posts WHERE user_id = $1");
+    sqlx::query("SELECT id FROM share_links WHERE id = $1 FOR UPDATE")
+        .bind(share_link_id)
+        .execute(&mut *tx)
+        .await?;
 
-    if search.is_some() {
-        query.push_str(" AND (title ILIKE $4 OR content ILIKE $4)");
-        count_query.push_str(" AND (title ILIKE $4 OR content ILIKE $4)");
-    }
-    if date_from.is_some() {
-        query.push_str(" AND created_at >= $5::timestamp");
-        count_query.push_str(" AND created_at >= $5::timestamp");
-    }
-    if date_to.is_some() {
-        query.push_str(" AND created_at <= $6::timestamp");
-        count_query.push_str(" AND created_at <= $6::timestamp");
+    let failures: (i64,) = sqlx::query_as(
+        r#"SELECT COUNT(*) FROM share_link_views
+           WHERE share_link_id = $1 AND NOT granted AND viewed_at > NOW() - make_interval(mins => $2)"#
+    )
+    .bind(share_link_id)
+    .bind(window_minutes)
+    .fetch_one(&mut *tx)
+    .await?;
+    if failures.0 >= max_failures {
+        return Ok(None);
     }
 
-    query.push_str(" ORDER BY created_at DESC LIMIT $2 OFFSET $3");
+    let id = Uuid::new_v4();
+    sqlx::query(
+        r#"INSERT INTO share_link_views (id, share_link_id, granted, ip_address, user_agent, viewed_at)
+           VALUES ($1, $2, FALSE, $3, $4, NOW())"#
+    )
+    .bind(id)
+    .bind(share_link_id)
+    .bind(ip_address)
+    .bind(user_agent)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(Some(id))
+}
 
-    let search_pattern = search.map(|s| format!("%{}%", s));
+/// `begin_share_attempt` で記録した試行を成功にする
+pub async fn grant_share_view(pool: &PgPool, view_id: &Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE share_link_views SET granted = TRUE WHERE id = $1")
+        .bind(view_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
 
-    let posts = sqlx::query_as::<_, Post>(&query)
-        .bind(user_id)
-        .bind(per_page)
-        .bind(offset)
-        .bind(&search_pattern)
-        .bind(date_from)
-        .bind(date_to)
+pub async fn get_share_link_views(pool: &PgPool, share_link_id: &Uuid) -> Result<Vec<ShareLinkView>, sqlx::Error> {
+    sqlx::query_as::<_, ShareLinkView>(
+        "SELECT id, granted, ip_address, user_agent, viewed_at FROM share_link_views WHERE share_link_id = $1 ORDER BY viewed_at DESC"
+    )
+    .bind(share_link_id)
+    .fetch_all(pool)
+    .await
+}
+
+// Reader grants
+
+const READER_GRANT_SELECT: &str = r#"SELECT g.id, g.owner_id, o.username AS owner_username, g.reader_id, r.username AS reader_username,
+    ARRAY(SELECT n.notebook_id FROM reader_grant_notebooks n WHERE n.grant_id = g.id ORDER BY n.notebook_id) AS notebook_ids,
+    g.analysis_summaries, g.created_at, g.updated_at
+    FROM reader_grants g JOIN users o ON o.id = g.owner_id JOIN users r ON r.id = g.reader_id"#;
+
+/// ユーザーが共有している閲覧者の一覧
+pub async fn get_reader_grants_by_owner(pool: &PgPool, owner_id: &Uuid) -> Result<Vec<ReaderGrant>, sqlx::Error> {
+    let query = format!("{} WHERE g.owner_id = $1 ORDER BY r.username", READER_GRANT_SELECT);
+    sqlx::query_as::<_, ReaderGrant>(&query)
+        .bind(owner_id)
         .fetch_all(pool)
+        .await
+}
+
+/// ユーザーに共有されている一覧
+pub async fn get_reader_grants_for_reader(pool: &PgPool, reader_id: &Uuid) -> Result<Vec<ReaderGrant>, sqlx::Error> {
+    let query = format!("{} WHERE g.reader_id = $1 ORDER BY o.username", READER_GRANT_SELECT);
+    sqlx::query_as::<_, ReaderGrant>(&query)
+        .bind(reader_id)
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn get_reader_grant(pool: &PgPool, owner_id: &Uuid, reader_id: &Uuid) -> Result<Option<ReaderGrant>, sqlx::Error> {
+    let query = format!("{} WHERE g.owner_id = $1 AND g.reader_id = $2", READER_GRANT_SELECT);
+    sqlx::query_as::<_, ReaderGrant>(&query)
+        .bind(owner_id)
+        .bind(reader_id)
+        .fetch_optional(pool)
+        .await
+}
+
+async fn get_reader_grant_by_id(conn: &mut PgConnection, id: &Uuid) -> Result<Option<ReaderGrant>, sqlx::Error> {
+    let query = format!("{} WHERE g.id = $1", READER_GRANT_SELECT);
+    sqlx::query_as::<_, ReaderGrant>(&query)
+        .bind(id)
+        .fetch_optional(conn)
+        .await
+}
+
+/// 閲覧を許可するノートを置き換える（所有者のノートのみ）
+async fn set_reader_grant_notebooks(conn: &mut PgConnection, grant_id: &Uuid, owner_id: &Uuid, notebook_ids: &[Uuid]) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM reader_grant_notebooks WHERE grant_id = $1")
+        .bind(grant_id)
+        .execute(&mut *conn)
         .await?;
 
-    let total: (i64,) = sqlx::query_as(&count_query)
-        .bind(user_id)
-        .bind(&search_pattern)
-        .bind(date_from)
-        .bind(date_to)
-        .fetch_one(pool)
+    sqlx::query(
+        r#"INSERT INTO reader_grant_notebooks (grant_id, notebook_id)
+           SELECT $1, id FROM notebooks WHERE user_id = $2 AND id = ANY($3)"#
+    )
+    .bind(grant_id)
+    .bind(owner_id)
+    .bind(notebook_ids)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// 閲覧者に共有を設定し、共有するノートを記録する
+///
+/// 共有は共有元と閲覧者の組ごとに1件のため、すでに共有している閲覧者には `None` を返す。
+pub async fn create_reader_grant(pool: &PgPool, owner_id: &Uuid, reader_id: &Uuid, notebook_ids: &[Uuid], analysis_summaries: bool) -> Result<Option<ReaderGrant>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let created: Option<(Uuid,)> = sqlx::query_as(
+        r#"INSERT INTO reader_grants (id, owner_id, reader_id, analysis_summaries, created_at, updated_at)
+           VALUES ($1, $2, $3, $4, NOW(), NOW())
+           ON CONFLICT (owner_id, reader_id) DO NOTHING
+           RETURNING id"#
+    )
+    .bind(Uuid::new_v4())
+    .bind(owner_id)
+    .bind(reader_id)
+    .bind(analysis_summaries)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some((id,)) = created else {
+        return Ok(None);
+    };
+    set_reader_grant_notebooks(&mut tx, &id, owner_id, notebook_ids).await?;
+    let grant = get_reader_grant_by_id(&mut tx, &id).await?;
+
+    tx.commit().await?;
+    Ok(grant)
+}
+
+pub async fn update_reader_grant(pool: &PgPool, id: &Uuid, owner_id: &Uuid, notebook_ids: &[Uuid], analysis_summaries: bool) -> Result<Option<ReaderGrant>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let updated = sqlx::query("UPDATE reader_grants SET analysis_summaries = $3, updated_at = NOW() WHERE id = $1 AND owner_id = $2")
+        .bind(id)
+        .bind(owner_id)
+        .bind(analysis_summaries)
+        .execute(&mut *tx)
         .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(None);
+    }
+    set_reader_grant_notebooks(&mut tx, id, owner_id, notebook_ids).await?;
+    let grant = get_reader_grant_by_id(&mut tx, id).await?;
 
-    Ok((posts, total.0))
+    tx.commit().await?;
+    Ok(grant)
 }
 
-pub async fn get_post_by_id(pool: &PgPool, id: &Uuid, user_id: &Uuid) -> Result<Option<Post>, sqlx::Error> {
-    sqlx::query_as::<_, Post>("SELECT * FROM posts WHERE id = $1 AND user_id = $2")
+/// 共有を取り消す（閲覧記録は残る）
+pub async fn delete_reader_grant(pool: &PgPool, id: &Uuid, owner_id: &Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM reader_grants WHERE id = $1 AND owner_id = $2")
         .bind(id)
+        .bind(owner_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn log_reader_access(pool: &PgPool, owner_id: &Uuid, reader_id: &Uuid, resource: &str, resource_id: Option<&Uuid>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"INSERT INTO reader_access_log (id, owner_id, reader_id, resource, resource_id, viewed_at)
+           VALUES ($1, $2, $3, $4, $5, NOW())"#
+    )
+    .bind(Uuid::new_v4())
+    .bind(owner_id)
+    .bind(reader_id)
+    .bind(resource)
+    .bind(resource_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// 閲覧者が読んだ複数の項目（一覧で返した投稿など）を記録する
+pub async fn log_reader_access_many(pool: &PgPool, owner_id: &Uuid, reader_id: &Uuid, resource: &str, resource_ids: &[Uuid]) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"INSERT INTO reader_access_log (id, owner_id, reader_id, resource, resource_id, viewed_at)
+           SELECT gen_random_uuid(), $1, $2, $3, resource_id, NOW() FROM UNNEST($4::uuid[]) AS resource_id"#
+    )
+    .bind(owner_id)
+    .bind(reader_id)
+    .bind(resource)
+    .bind(resource_ids)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_reader_access_log(pool: &PgPool, owner_id: &Uuid, reader_id: Option<&Uuid>, limit: i64) -> Result<Vec<ReaderAccessEntry>, sqlx::Error> {
+    sqlx::query_as::<_, ReaderAccessEntry>(
+        r#"SELECT l.id, l.reader_id, u.username AS reader_username, l.resource, l.resource_id, l.viewed_at
+           FROM reader_access_log l JOIN users u ON u.id = l.reader_id
+           WHERE l.owner_id = $1 AND ($2::uuid IS NULL OR l.reader_id = $2)
+           ORDER BY l.viewed_at DESC
+           LIMIT $3"#
+    )
+    .bind(owner_id)
+    .bind(reader_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+// Moods
+pub async fn get_mood_labels(pool: &PgPool, user_id: &Uuid) -> Result<Vec<(String, String)>, sqlx::Error> {
+    sqlx::query_as("SELECT mood, label FROM mood_labels WHERE user_id = $1")
         .bind(user_id)
-        .fetch_optional(pool)
+        .fetch_all(pool)
         .await
 }
 
-pub async fn update_post(pool: &PgPool, id: &Uuid, user_id: &Uuid, title: Option<&str>, content: Option<&str>, mood: Option<&str>, image_urls: Option<&[String]>) -> Result<Option<Post>, sqlx::Error> {
-    let image_urls_json = image_urls.map(|urls| serde_json::json!(urls));
-
-    sqlx::query_as::<_, Post>(
-        r#"UPDATE posts SET
-           title = COALESCE($3, title),
-           content = COALESCE($4, content),
-           mood = COALESCE($5, mood),
-           image_urls = COALESCE($6, image_urls),
-           updated_at = NOW()
-           WHERE id = $1 AND user_id = $2
-           RETURNING *"#
+pub async fn set_mood_label(pool: &PgPool, user_id: &Uuid, mood: &str, label: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"INSERT INTO mood_labels (user_id, mood, label) VALUES ($1, $2, $3)
+           ON CONFLICT (user_id, mood) DO UPDATE SET label = EXCLUDED.label"#
     )
-    .bind(id)
     .bind(user_id)
-    .bind(title)
-    .bind(content)
     .bind(mood)
-    .bind(image_urls_json)
-    .fetch_optional(pool)
-    .await
+    .bind(label)
+    .execute(pool)
+    .await?;
+    Ok(())
 }
 
-pub async fn delete_post(pool: &PgPool, id: &Uuid, user_id: &Uuid) -> Result<bool, sqlx::Error> {
-    let result = sqlx::query("DELETE FROM posts WHERE id = $1 AND user_id = $2")
-        .bind(id)
+pub async fn delete_mood_label(pool: &PgPool, user_id: &Uuid, mood: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM mood_labels WHERE user_id = $1 AND mood = $2")
         .bind(user_id)
+        .bind(mood)
         .execute(pool)
         .await?;
-    Ok(result.rows_affected() > 0)
+    Ok(())
+}
+
+/// 期間内の気分ごとの投稿数と平均の強さ（日付は `time_zone` での日付）
+///
+/// `notebooks` を指定するとそれらのノートの投稿のみを数える。
+pub async fn get_mood_counts(pool: &PgPool, user_id: &Uuid, date_from: Option<NaiveDate>, date_to: Option<NaiveDate>, time_zone: &str, notebooks: Option<&[Uuid]>) -> Result<Vec<MoodCount>, sqlx::Error> {
+    sqlx::query_as::<_, MoodCount>(
+        r#"SELECT mood, COUNT(*) AS count, AVG(mood_intensity)::float8 AS average_intensity
+           FROM posts
+           WHERE user_id = $1 AND mood IS NOT NULL AND deleted_at IS NULL AND status = 'published'
+             AND ($2::date IS NULL OR (entry_date AT TIME ZONE $4)::date >= $2)
+             AND ($3::date IS NULL OR (entry_date AT TIME ZONE $4)::date <= $3)
+             AND ($5::uuid[] IS NULL OR notebook_id = ANY($5))
+           GROUP BY mood
+           ORDER BY count DESC"#
+    )
+    .bind(user_id)
+    .bind(date_from)
+    .bind(date_to)
+    .bind(time_zone)
+    .bind(notebooks)
+    .fetch_all(pool)
+    .await
+}
+
+// Calendar
+
+/// `[start, end)` の日ごとの投稿数・気分・感情・TODO達成率（日付は `time_zone` での日付）
+///
+/// `notebooks` を指定すると投稿はそれらのノートのものだけを数える（TODOはノートに属さない）。
+/// `include_todos` が偽の場合はTODOを数えない。
+pub async fn get_calendar_days(pool: &PgPool, user_id: &Uuid, start: NaiveDate, end: NaiveDate, time_zone: &str, notebooks: Option<&[Uuid]>, include_todos: bool) -> Result<Vec<CalendarDay>, sqlx::Error> {
+    let mut days = sqlx::query_as::<_, CalendarDay>(
+        r#"WITH day_posts AS (
+               SELECT id, mood, (entry_date AT TIME ZONE $4)::date AS day
+               FROM posts
+               WHERE user_id = $1 AND deleted_at IS NULL AND status = 'published'
+                 AND entry_date >= $2::timestamp AT TIME ZONE $4
+                 AND entry_date < $3::timestamp AT TIME ZONE $4
+                 AND ($5::uuid[] IS NULL OR notebook_id = ANY($5))
+           ),
+           post_days AS (
+               SELECT day, COUNT(*) AS post_count,
+                      MODE() WITHIN GROUP (ORDER BY mood) AS dominant_mood
+               FROM day_posts
+               GROUP BY day
+           ),
+           todo_days AS (
+               SELECT date AS day, COUNT(*) AS todo_count, COUNT(*) FILTER (WHERE completed) AS todo_completed
+               FROM todos
+               WHERE user_id = $1 AND date >= $2 AND date < $3 AND $6
+               GROUP BY date
+           )
+           SELECT COALESCE(p.day, t.day) AS date,
+                  COALESCE(p.post_count, 0) AS post_count,
+                  p.dominant_mood,
+                  NULL::float8 AS average_joy,
+                  NULL::float8 AS average_sadness,
+                  COALESCE(t.todo_count, 0) AS todo_count,
+                  COALESCE(t.todo_completed, 0) AS todo_completed,
+                  t.todo_completed::float8 / NULLIF(t.todo_count, 0) AS todo_completion_ratio
+           FROM post_days p
+           FULL OUTER JOIN todo_days t ON t.day = p.day
+           ORDER BY date"#
+    )
+    .bind(user_id)
+    .bind(start)
+    .bind(end)
+    .bind(time_zone)
+    .bind(notebooks)
+    .bind(include_todos)
+    .fetch_all(pool)
+    .await?;
+
+    // 分析結果は暗号化して保存しているため、感情の平均は復号してから求める
+    let day_posts: Vec<(Uuid, NaiveDate)> = sqlx::query_as(
+        r#"SELECT id, (entry_date AT TIME ZONE $4)::date
+           FROM posts
+           WHERE user_id = $1 AND deleted_at IS NULL AND status = 'published'
+             AND entry_date >= $2::timestamp AT TIME ZONE $4
+             AND entry_date < $3::timestamp AT TIME ZONE $4
+             AND ($5::uuid[] IS NULL OR notebook_id = ANY($5))"#
+    )
+    .bind(user_id)
+    .bind(start)
+    .bind(end)
+    .bind(time_zone)
+    .bind(notebooks)
+    .fetch_all(pool)
+    .await?;
+    let post_ids: Vec<Uuid> = day_posts.iter().map(|(id, _)| *id).collect();
+    let results = get_latest_analysis_results(pool, user_id, &post_ids).await?;
+
+    let mut emotions: HashMap<NaiveDate, [(f64, u32); 2]> = HashMap::new();
+    for (post_id, day) in &day_posts {
+        let Some(result) = results.get(post_id) else {
+            continue;
+        };
+        let sums = emotions.entry(*day).or_default();
+        for (sum, emotion) in sums.iter_mut().zip(["joy", "sadness"]) {
+            if let Some(value) = result["emotions"][emotion].as_f64() {
+                sum.0 += value;
+                sum.1 += 1;
+            }
+        }
+    }
+
+    let average = |(sum, count): (f64, u32)| (count > 0).then(|| sum / f64::from(count));
+    for day in &mut days {
+        if let Some([joy, sadness]) = emotions.get(&day.date) {
+            day.average_joy = average(*joy);
+            day.average_sadness = average(*sadness);
+        }
+    }
+    Ok(days)
+}
+
+// Memories
+
+/// 前年以前の同じ日付（前後 `window_days` 日以内）の投稿を新しい順に返す
+///
+/// 日付は `time_zone` での日付。経過年数は記録日に暦の上で何年足すと `date` の前後に来るかで数える。
+/// 2月29日の投稿は、うるう年以外の年では2月28日の投稿と同じ日に表示する。
+/// `notebooks` を指定するとそれらのノートの投稿のみを返す。
+pub async fn get_memories(pool: &PgPool, user_id: &Uuid, date: NaiveDate, window_days: i32, time_zone: &str, notebooks: Option<&[Uuid]>) -> Result<Vec<Memory>, sqlx::Error> {
+    // 日付に年数を足すと、存在しない2月29日はその月の末日（2月28日）になる。
+    // 前後の期間が年をまたぐ場合があるため、年の差の前後1年も候補にする
+    let query = format!(
+        r#"SELECT posts.*, {}, y.years_ago,
+                  NULL::text AS analysis_summary,
+                  NULL::jsonb AS emotions
+           FROM posts
+           CROSS JOIN LATERAL (SELECT (entry_date AT TIME ZONE $4)::date AS local_date) d
+           CROSS JOIN LATERAL (
+               SELECT k AS years_ago
+               FROM generate_series(
+                   extract(year FROM $2::date)::int - extract(year FROM d.local_date)::int - 1,
+                   extract(year FROM $2::date)::int - extract(year FROM d.local_date)::int + 1
+               ) AS k
+               WHERE k >= 1 AND abs((d.local_date + make_interval(years => k))::date - $2::date) <= $3
+           ) y
+           WHERE posts.user_id = $1 AND posts.deleted_at IS NULL AND posts.status = 'published'
+             AND ($5::uuid[] IS NULL OR posts.notebook_id = ANY($5))
+           ORDER BY posts.entry_date DESC"#,
+        POST_TAGS_COLUMN
+    );
+    let mut memories = sqlx::query_as::<_, Memory>(&query)
+        .bind(user_id)
+        .bind(date)
+        .bind(window_days)
+        .bind(time_zone)
+        .bind(notebooks)
+        .fetch_all(pool)
+        .await?;
+
+    let key = data_key(pool, user_id).await?;
+    let post_ids: Vec<Uuid> = memories.iter().map(|m| m.post.id).collect();
+    let mut results = get_latest_analysis_results(pool, user_id, &post_ids).await?;
+    for memory in &mut memories {
+        open_post(&key, &mut memory.post)?;
+        if let Some(result) = results.remove(&memory.post.id) {
+            memory.analysis_summary = result["summary"].as_str().map(str::to_string);
+            memory.emotions = result.get("emotions").cloned();
+        }
+    }
+    Ok(memories)
+}
+
+// Writing stats
+
+/// 単語として1文字ずつ数える文字か（ひらがな・カタカナ・漢字）
+fn is_cjk(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{30ff}' | '\u{3400}'..='\u{4dbf}' | '\u{4e00}'..='\u{9fff}' | '\u{f900}'..='\u{faff}')
+}
+
+/// 本文の単語数
+///
+/// CJK文字は1文字を1語とし、それ以外は英数字の連なり（`'` `’` `-` でつながったものを含む）を1語とする。
+fn count_words(text: &str) -> i64 {
+    let is_word_char = |c: char| c.is_alphanumeric() && !is_cjk(c);
+    let mut words = 0;
+    let mut in_word = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if is_cjk(c) {
+            words += 1;
+            in_word = false;
+        } else if is_word_char(c) {
+            if !in_word {
+                words += 1;
+                in_word = true;
+            }
+        } else if in_word && matches!(c, '\'' | '’' | '-') && chars.peek().is_some_and(|&next| is_word_char(next)) {
+            // 単語の途中のアポストロフィ・ハイフン
+        } else {
+            in_word = false;
+        }
+    }
+    words
+}
+
+/// 本文の文字数（空白を除く）
+fn count_characters(text: &str) -> i64 {
+    text.chars().filter(|c| !c.is_whitespace()).count() as i64
+}
+
+/// 投稿の単語数・文字数を保存する（エンドツーエンド暗号化された投稿は数えない）
+///
+/// `post` は復号済みであること。
+async fn store_text_counts(conn: &mut PgConnection, post: &Post) -> Result<(), sqlx::Error> {
+    let counts = match post.encryption {
+        Some(_) => None,
+        None => Some((count_words(&post.content) as i32, count_characters(&post.content) as i32)),
+    };
+    sqlx::query("UPDATE posts SET word_count = $2, character_count = $3 WHERE id = $1")
+        .bind(post.id)
+        .bind(counts.map(|c| c.0))
+        .bind(counts.map(|c| c.1))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// 単語数・文字数が未計算の投稿（追加前からある投稿）を数えて埋め、件数を返す
+///
+/// 起動時に呼ぶ。本文を復号する必要があるため、鍵の設定（`crypto::init`）の後に呼ぶこと。
+pub async fn backfill_text_counts(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let mut filled = 0;
+    loop {
+        let rows: Vec<(Uuid, Uuid, String, bool)> = sqlx::query_as(
+            r#"SELECT id, user_id, content, encrypted_at_rest IS TRUE FROM posts
+               WHERE word_count IS NULL AND encryption IS NULL
+               ORDER BY user_id LIMIT 500"#
+        )
+        .fetch_all(pool)
+        .await?;
+        if rows.is_empty() {
+            return Ok(filled);
+        }
+
+        let mut keys: HashMap<Uuid, DataKey> = HashMap::new();
+        for (id, user_id, content, sealed) in rows {
+            let key = match keys.get(&user_id) {
+                Some(key) => key.clone(),
+                None => {
+                    let key = data_key(pool, &user_id).await?;
+                    keys.insert(user_id, key.clone());
+                    key
+                }
+            };
+            let content = key.decrypt(&content, sealed)?;
+            sqlx::query("UPDATE posts SET word_count = $2, character_count = $3 WHERE id = $1")
+                .bind(id)
+                .bind(count_words(&content) as i32)
+                .bind(count_characters(&content) as i32)
+                .execute(pool)
+                .await?;
+            filled += 1;
+        }
+    }
+}
+
+/// 投稿数と本文の単語数・文字数（空白を除く）の合計
+///
+/// 日本語・中国語は単語を空白で区切らないため、CJK文字は1文字を1語として数え、
+/// それ以外は英数字の連なりを1語として数える。数は投稿の保存時に計算しておく。
+/// エンドツーエンド暗号化された投稿の本文は数えられないため、平均の分母（`counted_entries`）にも含めない。
+pub async fn get_writing_totals(pool: &PgPool, user_id: &Uuid, notebooks: Option<&[Uuid]>) -> Result<WritingTotals, sqlx::Error> {
+    sqlx::query_as::<_, WritingTotals>(
+        r#"SELECT COUNT(*) AS total_entries,
+                  COUNT(*) FILTER (WHERE encryption IS NULL) AS counted_entries,
+                  COALESCE(SUM(word_count), 0)::bigint AS total_words,
+                  COALESCE(SUM(character_count), 0)::bigint AS total_characters
+           FROM posts
+           WHERE user_id = $1 AND deleted_at IS NULL AND status = 'published'
+             AND ($2::uuid[] IS NULL OR notebook_id = ANY($2))"#
+    )
+    .bind(user_id)
+    .bind(notebooks)
+    .fetch_one(pool)
+    .await
+}
+
+/// 投稿した日（`time_zone` での日付）の連続日数を `(現在, 最長)` で返す
+///
+/// 現在の連続日数は今日または昨日で終わっているものだけを数える。
+pub async fn get_streaks(pool: &PgPool, user_id: &Uuid, time_zone: &str, today: NaiveDate, notebooks: Option<&[Uuid]>) -> Result<(i64, i64), sqlx::Error> {
+    sqlx::query_as(
+        r#"WITH days AS (
+               SELECT DISTINCT (entry_date AT TIME ZONE $2)::date AS day
+               FROM posts
+               WHERE user_id = $1 AND deleted_at IS NULL AND status = 'published'
+                 AND ($4::uuid[] IS NULL OR notebook_id = ANY($4))
+           ),
+           streaks AS (
+               SELECT MAX(day) AS last_day, COUNT(*) AS length
+               FROM (SELECT day, day - (ROW_NUMBER() OVER (ORDER BY day))::int AS grp FROM days) d
+               GROUP BY grp
+           )
+           SELECT COALESCE(MAX(length) FILTER (WHERE last_day >= $3::date - 1), 0) AS current_streak,
+                  COALESCE(MAX(length), 0) AS longest_streak
+           FROM streaks"#
+    )
+    .bind(user_id)
+    .bind(time_zone)
+    .bind(today)
+    .bind(notebooks)
+    .fetch_one(pool)
+    .await
+}
+
+/// 実際に書いた日時（`created_at`）で最も投稿の多い曜日と時間帯
+pub async fn get_most_active_times(pool: &PgPool, user_id: &Uuid, time_zone: &str, notebooks: Option<&[Uuid]>) -> Result<(Option<i32>, Option<i32>), sqlx::Error> {
+    sqlx::query_as(
+        r#"WITH local_times AS (
+               SELECT created_at AT TIME ZONE $2 AS written_at
+               FROM posts
+               WHERE user_id = $1 AND deleted_at IS NULL AND status = 'published' AND created_at IS NOT NULL
+                 AND ($3::uuid[] IS NULL OR notebook_id = ANY($3))
+           )
+           SELECT
+               (SELECT EXTRACT(DOW FROM written_at)::int FROM local_times GROUP BY 1 ORDER BY COUNT(*) DESC, 1 LIMIT 1),
+               (SELECT EXTRACT(HOUR FROM written_at)::int FROM local_times GROUP BY 1 ORDER BY COUNT(*) DESC, 1 LIMIT 1)"#
+    )
+    .bind(user_id)
+    .bind(time_zone)
+    .bind(notebooks)
+    .fetch_one(pool)
+    .await
+}
+
+/// 月ごとの投稿数（日記の日付の月、古い順）
+pub async fn get_monthly_counts(pool: &PgPool, user_id: &Uuid, time_zone: &str, notebooks: Option<&[Uuid]>) -> Result<Vec<MonthlyCount>, sqlx::Error> {
+    sqlx::query_as::<_, MonthlyCount>(
+        r#"SELECT to_char(entry_date AT TIME ZONE $2, 'YYYY-MM') AS month, COUNT(*) AS count
+           FROM posts
+           WHERE user_id = $1 AND deleted_at IS NULL AND status = 'published'
+             AND ($3::uuid[] IS NULL OR notebook_id = ANY($3))
+           GROUP BY month
+           ORDER BY month"#
+    )
+    .bind(user_id)
+    .bind(time_zone)
+    .bind(notebooks)
+    .fetch_all(pool)
+    .await
 }
 
 // Analyses
-pub async fn create_analysis(pool: &PgPool, post_id: &Uuid, user_id: &Uuid, result: serde_json::Value, tokens_used: i32, model_version: &str) -> Result<Analysis, sqlx::Error> {
-    sqlx::query_as::<_, Analysis>(
-        r#"INSERT INTO analyses (id, post_id, user_id, analysis_type, result, tokens_used, model_version, created_at)
-           VALUES ($1, $2, $3, 'full', $4, $5, $6, NOW())
+
+/// 分析結果は暗号化して保存し、`result` は復号して返す
+pub async fn create_analysis(pool: &PgPool, post_id: &Uuid, user_id: &Uuid, result: serde_json::Value, tokens_used: i32, model_version: &str, revision_id: Option<&Uuid>) -> Result<Analysis, sqlx::Error> {
+    let key = data_key(pool, user_id).await?;
+    let mut analysis = sqlx::query_as::<_, Analysis>(
+        r#"INSERT INTO analyses (id, post_id, user_id, analysis_type, result, encrypted_at_rest, tokens_used, model_version, revision_id, created_at)
+           VALUES ($1, $2, $3, 'full', $4, $8, $5, $6, $7, NOW())
            RETURNING *"#
     )
     .bind(Uuid::new_v4())
     .bind(post_id)
     .bind(user_id)
-    .bind(result)
+    .bind(key.encrypt_json(&result))
     .bind(tokens_used)
     .bind(model_version)
+    .bind(revision_id)
+    .bind(key.is_sealing())
     .fetch_one(pool)
-    .await
+    .await?;
+    analysis.result = result;
+    Ok(analysis)
 }
 
 pub async fn get_analysis_by_post(pool: &PgPool, post_id: &Uuid, user_id: &Uuid) -> Result<Option<Analysis>, sqlx::Error> {
-    sqlx::query_as::<_, Analysis>("SELECT * FROM analyses WHERE post_id = $1 AND user_id = $2 ORDER BY created_at DESC LIMIT 1")
-        .bind(post_id)
+    let Some(mut analysis) = sqlx::query_as::<_, Analysis>(
+        r#"SELECT a.* FROM analyses a JOIN posts p ON p.id = a.post_id
+           WHERE a.post_id = $1 AND a.user_id = $2 AND p.deleted_at IS NULL
+           ORDER BY a.created_at DESC LIMIT 1"#
+    )
+    .bind(post_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    open_analysis(&data_key(pool, user_id).await?, &mut analysis)?;
+    Ok(Some(analysis))
+}
+
+/// 記録日の新しい投稿から順に、投稿ごとの最新の分析を返す
+///
+/// 分析した日時ではなく記録日で選ぶため、過去の日記を後から分析しても直近の傾向には入らない。
+/// `notebooks` を指定するとそれらのノートの投稿の分析のみを返す。
+pub async fn get_user_analyses(pool: &PgPool, user_id: &Uuid, limit: i32, notebooks: Option<&[Uuid]>) -> Result<Vec<Analysis>, sqlx::Error> {
+    let mut analyses = sqlx::query_as::<_, Analysis>(
+        r#"SELECT a.* FROM (
+               SELECT DISTINCT ON (p.id) a.*, p.entry_date AS post_entry_date
+               FROM analyses a JOIN posts p ON p.id = a.post_id
+               WHERE a.user_id = $1 AND p.deleted_at IS NULL AND p.status = 'published'
+                 AND p.entry_date <= NOW()
+                 AND ($3::uuid[] IS NULL OR p.notebook_id = ANY($3))
+               ORDER BY p.id, a.created_at DESC
+           ) a
+           ORDER BY a.post_entry_date DESC LIMIT $2"#
+    )
+    .bind(user_id)
+    .bind(limit)
+    .bind(notebooks)
+    .fetch_all(pool)
+    .await?;
+    let key = data_key(pool, user_id).await?;
+    analyses.iter_mut().try_for_each(|analysis| open_analysis(&key, analysis))?;
+    Ok(analyses)
+}
+
+/// 投稿ごとの最新の分析結果（復号したもの）
+async fn get_latest_analysis_results(pool: &PgPool, user_id: &Uuid, post_ids: &[Uuid]) -> Result<HashMap<Uuid, serde_json::Value>, sqlx::Error> {
+    let rows: Vec<(Uuid, serde_json::Value, bool)> = sqlx::query_as(
+        r#"SELECT DISTINCT ON (post_id) post_id, result, encrypted_at_rest IS TRUE FROM analyses
+           WHERE post_id = ANY($1) AND user_id = $2
+           ORDER BY post_id, created_at DESC"#
+    )
+    .bind(post_ids)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    let key = data_key(pool, user_id).await?;
+    rows.into_iter()
+        .map(|(post_id, result, sealed)| Ok((post_id, key.decrypt_json(result, sealed)?)))
+        .collect()
+}
+
+pub async fn count_user_analyses(pool: &PgPool, user_id: &Uuid, notebooks: Option<&[Uuid]>) -> Result<i64, sqlx::Error> {
+    let result: (i64,) = sqlx::query_as(
+        r#"SELECT COUNT(DISTINCT p.id) FROM analyses a JOIN posts p ON p.id = a.post_id
+           WHERE a.user_id = $1 AND p.deleted_at IS NULL AND p.status = 'published' AND p.entry_date <= NOW()
+             AND ($2::uuid[] IS NULL OR p.notebook_id = ANY($2))"#
+    )
+    .bind(user_id)
+    .bind(notebooks)
+    .fetch_one(pool)
+    .await?;
+    Ok(result.0)
+}
+
+// Uploads
+
+/// アップロードを記録した結果
+#[derive(Debug)]
+pub enum UploadRecord {
+    Created(Upload),
+    /// 同じ内容のファイルをすでにアップロードしている
+    Duplicate(Upload),
+    /// 記録するとストレージ上限を超える
+    QuotaExceeded { used: i64, quota: i64 },
+}
+
+/// アップロードするファイルの情報
+#[derive(Debug)]
+pub struct NewUpload<'a> {
+    /// `image` / `audio`
+    pub media_kind: &'a str,
+    pub filename: &'a str,
+    pub content_type: &'a str,
+    pub size_bytes: i64,
+    pub content_hash: &'a str,
+}
+
+/// ストレージ上限を確認してアップロードを記録する
+///
+/// 同時にアップロードされても上限を超えないよう、ユーザーの行をロックして使用量の確認と記録を
+/// 1つのトランザクションで行う。`default_quota` はユーザーごとの上限が未設定の場合の上限。
+/// 同じ内容かの判定はユーザーごと・`media_kind`（`image` / `audio`）ごとに行う。
+pub async fn create_upload(pool: &PgPool, user_id: &Uuid, upload: &NewUpload<'_>, default_quota: i64) -> Result<UploadRecord, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let (quota,): (i64,) = sqlx::query_as("SELECT COALESCE(storage_quota_bytes, $2) FROM users WHERE id = $1 FOR UPDATE")
+        .bind(user_id)
+        .bind(default_quota)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    let existing = sqlx::query_as::<_, Upload>("SELECT * FROM uploads WHERE user_id = $1 AND media_kind = $2 AND content_hash = $3")
+        .bind(user_id)
+        .bind(upload.media_kind)
+        .bind(upload.content_hash)
+        .fetch_optional(&mut *tx)
+        .await?;
+    if let Some(existing) = existing {
+        return Ok(UploadRecord::Duplicate(existing));
+    }
+
+    let (used,): (i64,) = sqlx::query_as("SELECT COALESCE(SUM(size_bytes), 0)::BIGINT FROM uploads WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    if used + upload.size_bytes > quota {
+        return Ok(UploadRecord::QuotaExceeded { used, quota });
+    }
+
+    let created = sqlx::query_as::<_, Upload>(
+        r#"INSERT INTO uploads (id, user_id, filename, content_type, size_bytes, content_hash, media_kind, created_at)
+           VALUES ($1, $2, $3, $4, $5, $6, $7, NOW())
+           RETURNING *"#
+    )
+    .bind(Uuid::new_v4())
+    .bind(user_id)
+    .bind(upload.filename)
+    .bind(upload.content_type)
+    .bind(upload.size_bytes)
+    .bind(upload.content_hash)
+    .bind(upload.media_kind)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(UploadRecord::Created(created))
+}
+
+/// ファイルの保存に失敗したアップロードの記録を取り消す
+pub async fn delete_upload(pool: &PgPool, id: &Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM uploads WHERE id = $1")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn get_upload_by_filename(pool: &PgPool, user_id: &Uuid, filename: &str) -> Result<Option<Upload>, sqlx::Error> {
+    sqlx::query_as::<_, Upload>("SELECT * FROM uploads WHERE user_id = $1 AND filename = $2 LIMIT 1")
         .bind(user_id)
+        .bind(filename)
         .fetch_optional(pool)
         .await
 }
 
-pub async fn get_user_analyses(pool: &PgPool, user_id: &Uuid, limit: i32) -> Result<Vec<Analysis>, sqlx::Error> {
-    sqlx::query_as::<_, Analysis>("SELECT * FROM analyses WHERE user_id = $1 ORDER BY created_at DESC LIMIT $2")
+/// `filenames` のうちユーザーがアップロードしたものを返す
+pub async fn get_owned_upload_filenames(pool: &PgPool, user_id: &Uuid, filenames: &[String]) -> Result<Vec<String>, sqlx::Error> {
+    let rows: Vec<(String,)> = sqlx::query_as("SELECT DISTINCT filename FROM uploads WHERE user_id = $1 AND filename = ANY($2)")
         .bind(user_id)
-        .bind(limit)
+        .bind(filenames)
         .fetch_all(pool)
-        .await
+        .await?;
+    Ok(rows.into_iter().map(|r| r.0).collect())
 }
 
-pub async fn count_user_analyses(pool: &PgPool, user_id: &Uuid) -> Result<i64, sqlx::Error> {
-    let result: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM analyses WHERE user_id = $1")
+pub async fn get_storage_used(pool: &PgPool, user_id: &Uuid) -> Result<i64, sqlx::Error> {
+    let result: (i64,) = sqlx::query_as("SELECT COALESCE(SUM(size_bytes), 0)::BIGINT FROM uploads WHERE user_id = $1")
         .bind(user_id)
         .fetch_one(pool)
         .await?;
     Ok(result.0)
 }
 
+pub async fn get_storage_quota(pool: &PgPool, user_id: &Uuid) -> Result<Option<i64>, sqlx::Error> {
+    let result: Option<(Option<i64>,)> = sqlx::query_as("SELECT storage_quota_bytes FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(result.and_then(|r| r.0))
+}
+
 // Todos
 pub async fn get_todos(pool: &PgPool, user_id: &Uuid, date: NaiveDate) -> Result<Vec<Todo>, sqlx::Error> {
     sqlx::query_as::<_, Todo>("SELECT * FROM todos WHERE user_id = $1 AND date = $2 ORDER BY created_at")
@@ -224,3 +2605,50 @@ pub async fn delete_todo(pool: &PgPool, id: i32, user_id: &Uuid) -> Result<bool,
         .await?;
     Ok(result.rows_affected() > 0)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cjk_covers_kana_and_kanji() {
+        assert!(is_cjk('あ'));
+        assert!(is_cjk('カ'));
+        assert!(is_cjk('日'));
+        assert!(is_cjk('㐀'));
+        assert!(!is_cjk('a'));
+        assert!(!is_cjk('。'));
+        assert!(!is_cjk('한'));
+    }
+
+    #[test]
+    fn counts_latin_words() {
+        assert_eq!(count_words(""), 0);
+        assert_eq!(count_words("   "), 0);
+        assert_eq!(count_words("Hello, world!"), 2);
+        assert_eq!(count_words("one\ntwo\tthree"), 3);
+        assert_eq!(count_words("3 cups of tea"), 4);
+    }
+
+    #[test]
+    fn joins_apostrophes_and_hyphens_inside_words() {
+        assert_eq!(count_words("don't stop"), 2);
+        assert_eq!(count_words("it’s a well-known fact"), 4);
+        assert_eq!(count_words("'quoted' - dash"), 2);
+        assert_eq!(count_words("end-"), 1);
+    }
+
+    #[test]
+    fn counts_each_cjk_character_as_a_word() {
+        assert_eq!(count_words("今日は晴れ"), 5);
+        assert_eq!(count_words("今日、晴れ。"), 4);
+        assert_eq!(count_words("カフェでcoffeeを飲んだ"), 9);
+    }
+
+    #[test]
+    fn counts_characters_without_whitespace() {
+        assert_eq!(count_characters("a b\nc"), 3);
+        assert_eq!(count_characters("今日 は"), 3);
+        assert_eq!(count_characters(""), 0);
+    }
+}
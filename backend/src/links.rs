@@ -0,0 +1,136 @@
+//! 投稿本文中の `[[...]]` リンクの解析
+//!
+//! `[[ID]]` は投稿のIDで、`[[タイトル]]` はユーザーの投稿のタイトル（大文字小文字は区別しない）でリンクする。
+//! `[[リンク先|表示名]]` で表示名を付けられる。`|` と `]]` はリンクの区切りになるため、
+//! これらを含むタイトルにはタイトルではなくIDでリンクする。
+//! リンク先の解決とリンク表の管理は `db` モジュールで行う。
+
+use uuid::Uuid;
+
+/// リンク先として受け付けるタイトルの最大文字数
+const MAX_TITLE_LENGTH: usize = 200;
+
+/// 本文中の `[[...]]` リンクのリンク先
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkTarget {
+    /// `[[3f2c...]]`: IDで指定した投稿
+    Id(Uuid),
+    /// `[[タイトル]]`: そのタイトルのユーザーの投稿
+    Title(String),
+}
+
+/// 本文中の `[[リンク先]]` / `[[リンク先|表示名]]` のリンク先を、書かれた順に返す
+///
+/// 改行を含むもの、閉じていないものはリンクとして扱わない。
+/// `[[a [[b]]` のように入れ子になっている場合は内側の `[[b]]` をリンクとする。
+fn raw_targets(content: &str) -> Vec<&str> {
+    let mut targets = Vec::new();
+    let mut offset = 0;
+
+    while let Some(open) = content[offset..].find("[[") {
+        let inner_start = offset + open + 2;
+        let Some(close) = content[inner_start..].find("]]") else {
+            break;
+        };
+        let inner_end = inner_start + close;
+        let inner = &content[inner_start..inner_end];
+
+        // 途中の `[[` から新しいリンクの候補が始まる
+        if let Some(nested) = inner.rfind("[[") {
+            offset = inner_start + nested;
+            continue;
+        }
+
+        if !inner.contains('\n') {
+            let target = inner.split_once('|').map_or(inner, |(target, _label)| target);
+            targets.push(target.trim());
+        }
+        offset = inner_end + 2;
+    }
+
+    targets
+}
+
+fn parse_target(target: &str) -> Option<LinkTarget> {
+    if target.is_empty() || target.chars().count() > MAX_TITLE_LENGTH {
+        return None;
+    }
+    Some(match Uuid::parse_str(target) {
+        Ok(id) => LinkTarget::Id(id),
+        Err(_) => LinkTarget::Title(target.to_string()),
+    })
+}
+
+/// 本文中のリンク先を重複を除いて書かれた順に返す（タイトルの重複は大文字小文字を区別しない）
+pub fn parse(content: &str) -> Vec<LinkTarget> {
+    let mut targets: Vec<LinkTarget> = Vec::new();
+    for target in raw_targets(content).into_iter().filter_map(parse_target) {
+        let duplicate = targets.iter().any(|t| match (t, &target) {
+            (LinkTarget::Title(a), LinkTarget::Title(b)) => a.to_lowercase() == b.to_lowercase(),
+            (a, b) => a == b,
+        });
+        if !duplicate {
+            targets.push(target);
+        }
+    }
+    targets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn title(value: &str) -> LinkTarget {
+        LinkTarget::Title(value.to_string())
+    }
+
+    #[test]
+    fn parses_titles_and_ids() {
+        let id = Uuid::new_v4();
+        let content = format!("昨日の[[旅行]]と[[{}]]の続き", id);
+        assert_eq!(parse(&content), vec![title("旅行"), LinkTarget::Id(id)]);
+    }
+
+    #[test]
+    fn labels_are_not_part_of_the_target() {
+        assert_eq!(parse("[[ Trip | the trip ]]"), vec![title("Trip")]);
+        assert_eq!(parse("[[Trip|a|b]]"), vec![title("Trip")]);
+    }
+
+    #[test]
+    fn duplicates_are_case_insensitive() {
+        assert_eq!(parse("[[Trip]] [[trip]] [[TRIP|label]] [[Other]]"), vec![title("Trip"), title("Other")]);
+    }
+
+    #[test]
+    fn ignores_empty_unclosed_and_multiline_links() {
+        assert!(parse("[[]] [[ ]] [[|label]]").is_empty());
+        assert!(parse("[[unclosed").is_empty());
+        assert!(parse("[[line\nbreak]]").is_empty());
+        assert!(parse("[single] [[").is_empty());
+    }
+
+    #[test]
+    fn nested_brackets_link_the_innermost() {
+        assert_eq!(parse("[[outer [[inner]]"), vec![title("inner")]);
+        assert_eq!(parse("[[[[a]]]]"), vec![title("a")]);
+    }
+
+    #[test]
+    fn parsing_continues_after_skipped_links() {
+        assert_eq!(parse("[[a\nb]] [[c]]"), vec![title("c")]);
+    }
+
+    #[test]
+    fn rejects_overlong_titles() {
+        let long = "あ".repeat(MAX_TITLE_LENGTH + 1);
+        assert!(parse(&format!("[[{}]]", long)).is_empty());
+        let max = "あ".repeat(MAX_TITLE_LENGTH);
+        assert_eq!(parse(&format!("[[{}]]", max)), vec![title(&max)]);
+    }
+
+    #[test]
+    fn handles_multibyte_text_around_links() {
+        assert_eq!(parse("絵文字🎉[[日記]]🎉の後"), vec![title("日記")]);
+    }
+}
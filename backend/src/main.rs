@@ -3,12 +3,21 @@ mod auth;
 mod db;
 mod models;
 mod ai;
+mod transcription;
+mod autosave;
+mod timezone;
+mod links;
+mod render;
+mod crypto;
 
 use axum::{
+    extract::DefaultBodyLimit,
+    middleware,
     routing::{get, post, put, delete},
     Router,
 };
 use sqlx::postgres::PgPoolOptions;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tower_http::cors::{CorsLayer, Any};
 use tower_http::services::ServeDir;
@@ -18,6 +27,21 @@ pub struct AppState {
     pub db: sqlx::PgPool,
     pub jwt_secret: String,
     pub gemini_api_key: String,
+    /// 1ファイルあたりのアップロード上限（バイト）
+    pub max_upload_bytes: i64,
+    /// ユーザーごとのストレージ上限のデフォルト値（バイト）
+    pub storage_quota_bytes: i64,
+    /// AI分析に含める画像の合計サイズ上限（バイト）
+    pub analysis_image_budget_bytes: i64,
+    /// 音声ファイル1件あたりのアップロード上限（バイト）
+    pub max_audio_upload_bytes: i64,
+    pub transcriber: Arc<dyn transcription::TranscriptionProvider>,
+    /// ゴミ箱内の投稿を保持する日数
+    pub trash_retention_days: i32,
+    /// 書き込み待ちの下書きの自動保存
+    pub autosave: autosave::AutosaveBuffer,
+    /// リバースプロキシが付けた接続元のヘッダー（`X-Forwarded-For` など）を信頼するか
+    pub trust_proxy_headers: bool,
 }
 
 #[tokio::main]
@@ -40,18 +64,111 @@ async fn main() -> anyhow::Result<()> {
         .await?;
 
     tracing::info!("Connected to database");
+    sqlx::migrate!("./migrations").run(&pool).await?;
+
+    crypto::init(crypto::Keyring::from_env()?);
+    match crypto::keyring().current_key_id() {
+        Some(id) => tracing::info!("Encryption at rest enabled (master key {})", id),
+        None => tracing::warn!("ENCRYPTION_MASTER_KEY is not set; journal content is stored unencrypted"),
+    }
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(command) = args.first() {
+        match command.as_str() {
+            "rotate-keys" => return rotate_keys(&pool, args.iter().any(|a| a == "--data-keys")).await,
+            other => anyhow::bail!("Unknown command: {}", other),
+        }
+    }
+
+    // 稼働中はデータ鍵のローテーションを拒否する（終了まで接続を保持する）
+    let _data_key_lock = db::lock_data_keys_shared(&pool).await?;
+    let sealed = db::seal_existing_data(&pool).await?;
+    if sealed > 0 {
+        tracing::info!("Encrypted {} rows stored before encryption at rest was enabled", sealed);
+    }
+
+    let hashed = db::backfill_title_hashes(&pool).await?;
+    if hashed > 0 {
+        tracing::info!("Computed title hashes of {} existing posts and links", hashed);
+    }
+
+    let counted = db::backfill_text_counts(&pool).await?;
+    if counted > 0 {
+        tracing::info!("Counted words and characters of {} existing posts", counted);
+    }
+
     tokio::fs::create_dir_all("uploads").await?;
 
     let state = Arc::new(AppState {
         db: pool,
         jwt_secret: std::env::var("JWT_SECRET_KEY").unwrap_or_else(|_| "secret".to_string()),
         gemini_api_key: std::env::var("GEMINI_API_KEY").unwrap_or_default(),
+        max_upload_bytes: std::env::var("MAX_UPLOAD_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(10 * 1024 * 1024),
+        storage_quota_bytes: std::env::var("STORAGE_QUOTA_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(500 * 1024 * 1024),
+        analysis_image_budget_bytes: std::env::var("ANALYSIS_IMAGE_BUDGET_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(4 * 1024 * 1024),
+        max_audio_upload_bytes: std::env::var("MAX_AUDIO_UPLOAD_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(25 * 1024 * 1024),
+        transcriber: transcription::from_env(),
+        trash_retention_days: std::env::var("TRASH_RETENTION_DAYS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(30),
+        autosave: autosave::AutosaveBuffer::default(),
+        trust_proxy_headers: std::env::var("TRUST_PROXY_HEADERS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(false),
+    });
+    tracing::info!("Transcription provider: {}", state.transcriber.name());
+
+    // 保持期間を過ぎたゴミ箱内の投稿を定期的に完全削除する
+    let purge_state = state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            match db::purge_expired_posts(&purge_state.db, purge_state.trash_retention_days).await {
+                Ok(0) => {}
+                Ok(purged) => tracing::info!("Purged {} expired posts from trash", purged),
+                Err(e) => tracing::error!("Failed to purge trash: {}", e),
+            }
+        }
+    });
+
+    // 自動保存された下書きをまとめて書き込む
+    let autosave_flush_secs = std::env::var("AUTOSAVE_FLUSH_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&secs: &u64| secs > 0)
+        .unwrap_or(5);
+    let autosave_state = state.clone();
+    let shutdown_state = state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(autosave_flush_secs));
+        loop {
+            interval.tick().await;
+            if let Err(e) = autosave_state.autosave.flush(&autosave_state.db).await {
+                tracing::error!("Failed to flush autosaved drafts: {}", e);
+            }
+        }
     });
 
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods(Any)
-        .allow_headers(Any);
+        .allow_headers(Any)
+        .expose_headers([axum::http::header::ETAG]);
 
     let app = Router::new()
         .route("/health", get(|| async { axum::Json(serde_json::json!({"status": "healthy"})) }))
@@ -60,20 +177,85 @@ async fn main() -> anyhow::Result<()> {
         .route("/api/v1/auth/me", get(api::auth::me))
         .route("/api/v1/posts", get(api::posts::list_posts))
         .route("/api/v1/posts", post(api::posts::create_post))
+        .route("/api/v1/posts/voice", post(api::posts::create_voice_post))
+        .route("/api/v1/posts/bulk", post(api::posts::bulk_posts))
         .route("/api/v1/posts/:id", get(api::posts::get_post))
         .route("/api/v1/posts/:id", put(api::posts::update_post))
         .route("/api/v1/posts/:id", delete(api::posts::delete_post))
+        .route("/api/v1/posts/:id/autosave", put(api::posts::autosave_draft))
+        .route("/api/v1/posts/:id/publish", post(api::posts::publish_post))
+        .route("/api/v1/posts/:id/rendered", get(api::posts::get_rendered_post))
+        .route("/api/v1/posts/:id/backlinks", get(api::posts::list_backlinks))
+        .route("/api/v1/posts/:id/shares", get(api::shares::list_share_links))
+        .route("/api/v1/posts/:id/shares", post(api::shares::create_share_link))
+        .route("/api/v1/shares/:id", delete(api::shares::revoke_share_link))
+        .route("/api/v1/shares/:id/views", get(api::shares::list_share_views))
+        .route("/api/v1/shared/:token", get(api::shares::view_shared_post))
+        .route("/api/v1/readers", get(api::readers::list_readers))
+        .route("/api/v1/readers", post(api::readers::create_reader))
+        .route("/api/v1/readers/log", get(api::readers::get_access_log))
+        .route("/api/v1/readers/:id", put(api::readers::update_reader))
+        .route("/api/v1/readers/:id", delete(api::readers::delete_reader))
+        .route("/api/v1/shared-with-me", get(api::readers::list_shared_with_me))
+        .route("/api/v1/posts/:id/revisions", get(api::revisions::list_revisions))
+        .route("/api/v1/posts/:id/revisions/diff", get(api::revisions::diff_revisions))
+        .route("/api/v1/posts/:id/revisions/:revision/restore", post(api::revisions::restore_revision))
+        .route("/api/v1/posts/:id/tags/from-analysis", post(api::tags::tag_from_analysis))
+        .route("/api/v1/trash", get(api::trash::list_trash))
+        .route("/api/v1/trash", delete(api::trash::empty_trash))
+        .route("/api/v1/trash/:id/restore", post(api::trash::restore_post))
+        .route("/api/v1/trash/:id", delete(api::trash::purge_post))
+        .route("/api/v1/tags", get(api::tags::list_tags))
+        .route("/api/v1/tags", post(api::tags::create_tag))
+        .route("/api/v1/tags/:id", put(api::tags::rename_tag))
+        .route("/api/v1/tags/:id", delete(api::tags::delete_tag))
+        .route("/api/v1/notebooks", get(api::notebooks::list_notebooks))
+        .route("/api/v1/notebooks", post(api::notebooks::create_notebook))
+        .route("/api/v1/notebooks/:id", put(api::notebooks::update_notebook))
+        .route("/api/v1/notebooks/:id", delete(api::notebooks::delete_notebook))
+        .route("/api/v1/templates", get(api::templates::list_templates))
+        .route("/api/v1/templates", post(api::templates::create_template))
+        .route("/api/v1/templates/:id", put(api::templates::update_template))
+        .route("/api/v1/templates/:id", delete(api::templates::delete_template))
+        .route("/api/v1/templates/:id/instantiate", post(api::templates::instantiate_template))
+        .route("/api/v1/prompts", get(api::prompts::list_prompts))
+        .route("/api/v1/prompts/generate", post(api::prompts::generate_prompts))
+        .route("/api/v1/moods", get(api::moods::list_moods))
+        .route("/api/v1/moods/stats", get(api::moods::get_mood_stats))
+        .route("/api/v1/moods/:mood", put(api::moods::update_mood_label))
+        .route("/api/v1/moods/:mood", delete(api::moods::reset_mood_label))
+        .route("/api/v1/calendar", get(api::calendar::get_calendar))
+        .route("/api/v1/stats", get(api::stats::get_writing_stats))
+        .route("/api/v1/memories", get(api::memories::get_memories))
         .route("/api/v1/analyses/create", post(api::analyses::create_analysis))
         .route("/api/v1/analyses/post/:post_id", get(api::analyses::get_analysis))
         .route("/api/v1/analyses/user/summary", get(api::analyses::get_user_summary))
-        .route("/api/v1/uploads/image", post(api::uploads::upload_image))
+        .route(
+            "/api/v1/uploads/image",
+            post(api::uploads::upload_image).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/api/v1/uploads/images",
+            post(api::uploads::upload_images).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/api/v1/uploads/audio",
+            post(api::uploads::upload_audio).layer(DefaultBodyLimit::disable()),
+        )
+        .route("/api/v1/uploads/usage", get(api::uploads::get_usage))
         .route("/api/v1/todos", get(api::todos::list_todos))
         .route("/api/v1/todos", post(api::todos::create_todo))
         .route("/api/v1/todos/:id", put(api::todos::update_todo))
         .route("/api/v1/todos/:id", delete(api::todos::delete_todo))
         .route("/api/v1/settings/models", get(api::settings::get_models))
+        .route("/api/v1/settings/preferences", get(api::settings::get_preferences))
+        .route("/api/v1/settings/preferences", put(api::settings::update_preferences))
+        .route("/api/v1/settings/encryption", get(api::encryption::get_encryption))
+        .route("/api/v1/settings/encryption", put(api::encryption::set_encryption))
+        .route("/api/v1/settings/encryption", delete(api::encryption::disable_encryption))
         .route("/api/v1/chat/message", post(api::chat::chat))
         .route("/api/v1/chat/summarize", post(api::chat::summarize))
+        .route_layer(middleware::from_fn(api::readers::reject_owner_header))
         .nest_service("/uploads", ServeDir::new("uploads"))
         .layer(cors)
         .with_state(state);
@@ -81,7 +263,64 @@ async fn main() -> anyhow::Result<()> {
     let addr = "0.0.0.0:8000";
     tracing::info!("Server running on {}", addr);
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    // 終了前に溜まっている自動保存を書き込む
+    match shutdown_state.autosave.flush(&shutdown_state.db).await {
+        Ok(count) => tracing::info!("Flushed {} autosaved drafts before shutdown", count),
+        Err(e) => tracing::error!("Failed to flush autosaved drafts before shutdown: {}", e),
+    }
+
+    Ok(())
+}
+
+/// SIGINT（Ctrl+C）または SIGTERM を待つ
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("failed to install Ctrl+C handler");
+    };
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    tracing::info!("Shutting down");
+}
+
+/// `soulmap-api rotate-keys [--data-keys]`
+///
+/// データ鍵を `ENCRYPTION_MASTER_KEY` で包み直す（旧マスター鍵は `ENCRYPTION_PREVIOUS_MASTER_KEYS` に指定する）。
+/// `--data-keys` を付けると全ユーザーのデータ鍵を作り直して保存済みのデータを暗号化し直す。
+/// 稼働中のサーバーは古いデータ鍵をキャッシュしているため、こちらはサーバーが動いていれば実行しない。
+async fn rotate_keys(pool: &sqlx::PgPool, data_keys: bool) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        crypto::keyring().is_enabled(),
+        "ENCRYPTION_MASTER_KEY must be set to rotate keys"
+    );
+    let _data_key_lock = if data_keys {
+        let lock = db::try_lock_data_keys(pool).await?;
+        Some(lock.ok_or_else(|| anyhow::anyhow!("The API server is running; stop it before rotating data keys"))?)
+    } else {
+        None
+    };
+
+    let rewrapped = db::rewrap_data_keys(pool).await?;
+    tracing::info!("Rewrapped {} data keys with the current master key", rewrapped);
+
+    if data_keys {
+        let user_ids = db::get_user_ids(pool).await?;
+        for user_id in &user_ids {
+            db::rotate_data_key(pool, user_id).await?;
+        }
+        tracing::info!("Rotated data keys and re-encrypted content for {} users", user_ids.len());
+    }
 
     Ok(())
 }
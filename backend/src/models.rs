@@ -1,5 +1,5 @@
-use chrono::{NaiveDate, NaiveDateTime};
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
 
@@ -11,7 +11,7 @@ pub struct User {
     #[serde(skip_serializing)]
     pub hashed_password: String,
     pub is_active: Option<bool>,
-    pub created_at: Option<NaiveDateTime>,
+    pub created_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone, FromRow, Serialize)]
@@ -21,9 +21,124 @@ pub struct Post {
     pub title: Option<String>,
     pub content: String,
     pub mood: Option<String>,
+    pub mood_intensity: Option<i16>,
     pub image_urls: serde_json::Value,
-    pub created_at: Option<NaiveDateTime>,
-    pub updated_at: Option<NaiveDateTime>,
+    pub audio_url: Option<String>,
+    /// `plain` または `markdown`
+    pub content_format: String,
+    /// エンドツーエンド暗号化された投稿の復号に必要な情報（`PostEncryption`、平文の投稿では省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<serde_json::Value>,
+    /// タイトルと本文を保存時の暗号化で暗号化しているか（`db` で復号した後は使わない）
+    #[serde(skip)]
+    pub encrypted_at_rest: Option<bool>,
+    /// `draft` または `published`
+    pub status: String,
+    /// 日記の日付（一覧の並び順・日付フィルタ・集計に使用）
+    pub entry_date: DateTime<Utc>,
+    pub notebook_id: Option<Uuid>,
+    /// 更新のたびに増えるバージョン（ETag として使用）
+    pub version: i32,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    /// ゴミ箱に移動した日時
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[sqlx(default)]
+    pub tags: Vec<String>,
+}
+
+/// 気分の段階（投稿の `mood` に保存される値）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mood {
+    Great,
+    Good,
+    Neutral,
+    Bad,
+    Awful,
+}
+
+impl Mood {
+    pub const ALL: [Mood; 5] = [Mood::Great, Mood::Good, Mood::Neutral, Mood::Bad, Mood::Awful];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Mood::Great => "great",
+            Mood::Good => "good",
+            Mood::Neutral => "neutral",
+            Mood::Bad => "bad",
+            Mood::Awful => "awful",
+        }
+    }
+
+    pub fn default_label(self) -> &'static str {
+        match self {
+            Mood::Great => "最高",
+            Mood::Good => "良い",
+            Mood::Neutral => "普通",
+            Mood::Bad => "悪い",
+            Mood::Awful => "最悪",
+        }
+    }
+
+    /// 気分の値（最悪 = 1 〜 最高 = 5）
+    pub fn score(self) -> i16 {
+        match self {
+            Mood::Great => 5,
+            Mood::Good => 4,
+            Mood::Neutral => 3,
+            Mood::Bad => 2,
+            Mood::Awful => 1,
+        }
+    }
+}
+
+/// 気分の強さの範囲
+pub const MOOD_INTENSITY_RANGE: std::ops::RangeInclusive<i16> = 1..=5;
+
+/// 投稿の公開状態（投稿の `status` に保存される値）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PostStatus {
+    Draft,
+    #[default]
+    Published,
+}
+
+impl PostStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PostStatus::Draft => "draft",
+            PostStatus::Published => "published",
+        }
+    }
+}
+
+/// 本文の書式（投稿の `content_format` に保存される値）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentFormat {
+    #[default]
+    Plain,
+    Markdown,
+}
+
+impl ContentFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ContentFormat::Plain => "plain",
+            ContentFormat::Markdown => "markdown",
+        }
+    }
+
+    /// DBに保存された値から変換する（不明な値はプレーンテキスト扱い）
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "markdown" => ContentFormat::Markdown,
+            _ => ContentFormat::Plain,
+        }
+    }
 }
 
 #[derive(Debug, Clone, FromRow, Serialize)]
@@ -33,9 +148,14 @@ pub struct Analysis {
     pub user_id: Uuid,
     pub analysis_type: String,
     pub result: serde_json::Value,
+    /// `result` を保存時の暗号化で暗号化しているか
+    #[serde(skip)]
+    pub encrypted_at_rest: Option<bool>,
     pub tokens_used: Option<i32>,
     pub model_version: Option<String>,
-    pub created_at: Option<NaiveDateTime>,
+    pub created_at: Option<DateTime<Utc>>,
+    /// 分析対象となった投稿のリビジョン
+    pub revision_id: Option<Uuid>,
 }
 
 #[derive(Debug, Clone, FromRow, Serialize)]
@@ -45,7 +165,200 @@ pub struct Todo {
     pub title: String,
     pub completed: bool,
     pub date: NaiveDate,
-    pub created_at: Option<NaiveDateTime>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct PostRevision {
+    pub id: Uuid,
+    pub post_id: Uuid,
+    pub user_id: Uuid,
+    pub revision_number: i32,
+    pub title: Option<String>,
+    pub content: String,
+    pub mood: Option<String>,
+    pub mood_intensity: Option<i16>,
+    pub image_urls: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<serde_json::Value>,
+    /// タイトルと本文を保存時の暗号化で暗号化しているか
+    #[serde(skip)]
+    pub encrypted_at_rest: Option<bool>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Tag {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct TagWithCount {
+    pub id: Uuid,
+    pub name: String,
+    pub post_count: i64,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Notebook {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct NotebookWithCount {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub post_count: i64,
+}
+
+/// 投稿テンプレート（`{{date}}` `{{weekday}}` `{{mood}}` を作成時に置き換える）
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Template {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub title: Option<String>,
+    pub content: String,
+    pub content_format: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// 書くきっかけになる問いかけ
+#[derive(Debug, Clone, Serialize)]
+pub struct WritingPrompt {
+    /// 組み込みのカテゴリ、またはAI生成の場合は `ai`
+    pub category: String,
+    pub text: String,
+}
+
+/// 投稿の共有リンク（トークンは作成時にのみ返す）
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct ShareLink {
+    pub id: Uuid,
+    pub post_id: Uuid,
+    pub has_password: bool,
+    pub include_analysis: bool,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+    /// 閲覧できた回数
+    pub view_count: i64,
+    pub last_viewed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedShareLink {
+    #[serde(flatten)]
+    pub link: ShareLink,
+    pub token: String,
+}
+
+/// 公開エンドポイントでの共有リンクの照合結果
+#[derive(Debug, Clone, FromRow)]
+pub struct ShareAccess {
+    pub id: Uuid,
+    pub post_id: Uuid,
+    pub user_id: Uuid,
+    pub password_hash: Option<String>,
+    pub include_analysis: bool,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct ShareLinkView {
+    pub id: Uuid,
+    pub granted: bool,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub viewed_at: Option<DateTime<Utc>>,
+}
+
+/// 共有リンクで公開される投稿
+#[derive(Debug, Serialize)]
+pub struct SharedPost {
+    pub title: Option<String>,
+    pub content: String,
+    pub content_format: ContentFormat,
+    pub rendered_html: String,
+    pub mood: Option<String>,
+    pub mood_intensity: Option<i16>,
+    pub image_urls: serde_json::Value,
+    pub entry_date: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub analysis: Option<serde_json::Value>,
+}
+
+/// 他のアカウントへの読み取り専用の共有
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct ReaderGrant {
+    pub id: Uuid,
+    pub owner_id: Uuid,
+    pub owner_username: String,
+    pub reader_id: Uuid,
+    pub reader_username: String,
+    /// 閲覧できるノート（この中の公開済み投稿と分析結果を読める）
+    pub notebook_ids: Vec<Uuid>,
+    /// 分析サマリーを閲覧できる
+    pub analysis_summaries: bool,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// 閲覧者が見た内容の記録
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct ReaderAccessEntry {
+    pub id: Uuid,
+    pub reader_id: Uuid,
+    pub reader_username: String,
+    /// `posts` `post` `analysis` `summary` `backlinks` `revisions` `memories` `calendar` `stats` `moods`
+    ///
+    /// 一覧（`posts` `memories`）は返した投稿ごとに記録する。
+    pub resource: String,
+    pub resource_id: Option<Uuid>,
+    pub viewed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Upload {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub filename: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    pub content_hash: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct UserPreferences {
+    pub analyze_images: bool,
+    /// IANA名（例: `Asia/Tokyo`）。「今日」や日付フィルタ、日別集計の基準になる
+    pub time_zone: String,
+}
+
+/// エンドツーエンド暗号化のデータ鍵（クライアントでパスフレーズから導出した鍵で包んだもの）
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct EncryptionKey {
+    #[serde(skip_serializing)]
+    pub enabled: bool,
+    /// 包んだデータ鍵（base64）
+    pub wrapped_key: String,
+    pub wrap_algorithm: String,
+    pub wrap_nonce: String,
+    /// パスフレーズからの鍵導出方式（`argon2id` など）とそのパラメータ
+    pub kdf: String,
+    pub kdf_salt: String,
+    pub kdf_params: serde_json::Value,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
 }
 
 // Request/Response DTOs
@@ -72,29 +385,214 @@ pub struct AuthResponse {
 pub struct CreatePostRequest {
     pub title: Option<String>,
     pub content: String,
-    pub mood: Option<String>,
+    pub mood: Option<Mood>,
+    pub mood_intensity: Option<i16>,
     pub image_urls: Option<Vec<String>>,
+    pub audio_url: Option<String>,
+    pub tags: Option<Vec<String>>,
+    /// 省略時は `plain`
+    pub content_format: Option<ContentFormat>,
+    /// `draft` を指定すると下書きとして作成する
+    pub status: Option<PostStatus>,
+    /// 省略時は現在日時
+    pub entry_date: Option<DateTime<Utc>>,
+    pub notebook_id: Option<Uuid>,
+    /// エンドツーエンド暗号化が有効な場合は必須（`title` と `content` は暗号文）
+    pub encryption: Option<PostEncryption>,
+}
+
+/// エンドツーエンド暗号化された投稿の情報
+///
+/// `content` と `title` はデータ鍵で暗号化したbase64の暗号文で、サーバーは復号できない。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostEncryption {
+    /// `aes-256-gcm` または `xchacha20-poly1305`
+    pub algorithm: String,
+    /// base64
+    pub content_nonce: String,
+    /// タイトルがない場合は省略
+    pub title_nonce: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateVoicePostRequest {
+    pub audio_url: String,
+    pub title: Option<String>,
+    pub mood: Option<Mood>,
+    pub mood_intensity: Option<i16>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct UpdatePostRequest {
     pub title: Option<String>,
     pub content: Option<String>,
-    pub mood: Option<String>,
+    pub mood: Option<Mood>,
+    pub mood_intensity: Option<i16>,
     pub image_urls: Option<Vec<String>>,
+    pub content_format: Option<ContentFormat>,
+    pub entry_date: Option<DateTime<Utc>>,
+    /// 省略するとノートは変わらず、`null` を指定するとノートから外す
+    #[serde(default, deserialize_with = "present")]
+    pub notebook_id: Option<Option<Uuid>>,
+    /// 指定された場合は投稿のタグをこの内容で置き換える
+    pub tags: Option<Vec<String>>,
+    /// エンドツーエンド暗号化が有効な場合、本文・タイトルを変更するときは必須
+    /// （`content` も必須で、`title` を省略するとタイトルなしになる）。
+    /// 暗号化された投稿を平文の `content` で保存すると平文の投稿に戻る
+    pub encryption: Option<PostEncryption>,
+}
+
+/// 省略されたフィールド（`None`）と `null`（`Some(None)`）を区別して読む
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// サーバーでHTMLに変換・サニタイズした投稿本文
+#[derive(Debug, Serialize)]
+pub struct RenderedPost {
+    pub id: Uuid,
+    pub content_format: ContentFormat,
+    pub rendered_html: String,
+    pub version: i32,
+}
+
+/// 一括操作の対象を絞り込む条件（投稿一覧と同じ意味）
+#[derive(Debug, Default, Deserialize)]
+pub struct BulkPostFilter {
+    pub search: Option<String>,
+    pub date_from: Option<String>,
+    pub date_to: Option<String>,
+    pub tag: Option<String>,
+}
+
+/// 一括操作の内容
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BulkAction {
+    /// ゴミ箱へ移動
+    Delete,
+    /// ノートを移動（`null` でノートから外す）
+    Move { notebook_id: Option<Uuid> },
+    Tag { tags: Vec<String> },
+    Untag { tags: Vec<String> },
+    /// AIで再分析
+    Analyze,
+}
+
+/// 投稿の一括操作（`ids` と `filter` のどちらか一方で対象を指定する）
+#[derive(Debug, Deserialize)]
+pub struct BulkPostRequest {
+    pub ids: Option<Vec<Uuid>>,
+    pub filter: Option<BulkPostFilter>,
+    pub action: BulkAction,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkItemResult {
+    pub id: Uuid,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkPostResponse {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub results: Vec<BulkItemResult>,
+}
+
+/// 下書きの自動保存（本文全体を送る）
+#[derive(Debug, Deserialize)]
+pub struct AutosaveRequest {
+    pub title: Option<String>,
+    pub content: String,
 }
 
 #[derive(Debug, Serialize)]
 pub struct PostListResponse {
     pub posts: Vec<Post>,
-    pub total: i64,
-    pub page: i32,
+    /// ページ番号モードのみ（カーソルモードでは件数を数えない）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<i32>,
     pub per_page: i32,
+    /// 次のページのカーソル（カーソルモードで続きがある場合のみ）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TagRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotebookRequest {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TemplateRequest {
+    pub name: String,
+    pub title: Option<String>,
+    pub content: String,
+    pub content_format: Option<ContentFormat>,
+}
+
+/// テンプレートから下書きを作成する
+#[derive(Debug, Deserialize)]
+pub struct InstantiateTemplateRequest {
+    /// 日記の日付（省略時はユーザーのタイムゾーンでの今日）
+    pub date: Option<NaiveDate>,
+    pub mood: Option<Mood>,
+    pub mood_intensity: Option<i16>,
+    pub notebook_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateShareLinkRequest {
+    /// 指定した場合、閲覧時に `X-Share-Password` ヘッダーで必要になる
+    pub password: Option<String>,
+    /// 分析結果も公開する
+    pub include_analysis: Option<bool>,
+    /// 有効期間（日数、省略時は7日）
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateReaderGrantRequest {
+    /// 閲覧者のSoulMapアカウントのメールアドレス
+    pub reader_email: String,
+    #[serde(default)]
+    pub notebook_ids: Vec<Uuid>,
+    #[serde(default)]
+    pub analysis_summaries: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateReaderGrantRequest {
+    pub notebook_ids: Vec<Uuid>,
+    pub analysis_summaries: bool,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct CreateAnalysisRequest {
     pub post_id: Uuid,
+    /// 暗号化された投稿を分析する場合に、クライアントで復号した内容（保存しない）
+    pub plaintext: Option<AnalysisPlaintext>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnalysisPlaintext {
+    pub title: Option<String>,
+    pub content: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -116,3 +614,181 @@ pub struct UserSummary {
     pub total_posts_analyzed: i64,
     pub summary: serde_json::Value,
 }
+
+#[derive(Debug, Serialize)]
+pub struct StorageUsage {
+    pub used_bytes: i64,
+    pub quota_bytes: i64,
+    pub remaining_bytes: i64,
+    pub max_file_bytes: i64,
+}
+
+/// エンドツーエンド暗号化の設定
+///
+/// 無効にした後も暗号化された投稿が残っている間は `key` を返す。
+#[derive(Debug, Serialize)]
+pub struct EncryptionSettings {
+    pub enabled: bool,
+    pub key: Option<EncryptionKey>,
+}
+
+/// エンドツーエンド暗号化を有効にする（パスフレーズ変更時は包み直した鍵で置き換える）
+#[derive(Debug, Deserialize)]
+pub struct SetEncryptionKeyRequest {
+    pub wrapped_key: String,
+    pub wrap_algorithm: String,
+    pub wrap_nonce: String,
+    pub kdf: String,
+    pub kdf_salt: String,
+    #[serde(default)]
+    pub kdf_params: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdatePreferencesRequest {
+    pub analyze_images: Option<bool>,
+    pub time_zone: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MoodOption {
+    pub mood: Mood,
+    pub label: String,
+    pub score: i16,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateMoodLabelRequest {
+    pub label: String,
+}
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct MoodCount {
+    pub mood: String,
+    pub count: i64,
+    pub average_intensity: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MoodStats {
+    pub moods: Vec<MoodCount>,
+    pub total: i64,
+    /// 各投稿の気分スコア（1〜5）の平均
+    pub average_score: Option<f64>,
+}
+
+/// カレンダーの1日分の活動量（活動のない日は含まない）
+#[derive(Debug, FromRow, Serialize)]
+pub struct CalendarDay {
+    pub date: NaiveDate,
+    pub post_count: i64,
+    /// その日最も多かった気分
+    pub dominant_mood: Option<String>,
+    /// 分析結果の喜び・悲しみ（0.0〜1.0）の平均
+    pub average_joy: Option<f64>,
+    pub average_sadness: Option<f64>,
+    pub todo_count: i64,
+    pub todo_completed: i64,
+    pub todo_completion_ratio: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CalendarResponse {
+    pub year: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub month: Option<u32>,
+    pub time_zone: String,
+    pub days: Vec<CalendarDay>,
+}
+
+/// 投稿にリンクしている投稿
+#[derive(Debug, FromRow, Serialize)]
+pub struct Backlink {
+    pub id: Uuid,
+    pub title: Option<String>,
+    /// `title` を保存時の暗号化で暗号化しているか
+    #[serde(skip)]
+    pub encrypted_at_rest: Option<bool>,
+    pub entry_date: DateTime<Utc>,
+}
+
+/// 過去の同じ日付の投稿と、その最新の分析結果
+#[derive(Debug, FromRow, Serialize)]
+pub struct Memory {
+    pub years_ago: i32,
+    #[sqlx(flatten)]
+    pub post: Post,
+    pub analysis_summary: Option<String>,
+    pub emotions: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MemoriesResponse {
+    pub date: NaiveDate,
+    /// 前後何日までを同じ日とみなしたか（週単位なら3）
+    pub window_days: i32,
+    pub memories: Vec<Memory>,
+}
+
+/// 投稿本文の文字数・単語数の合計
+#[derive(Debug, FromRow)]
+pub struct WritingTotals {
+    pub total_entries: i64,
+    /// 単語数・文字数を数えた投稿数（エンドツーエンド暗号化された投稿を除く）
+    pub counted_entries: i64,
+    pub total_words: i64,
+    pub total_characters: i64,
+}
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct MonthlyCount {
+    /// `YYYY-MM`
+    pub month: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WritingStats {
+    pub total_entries: i64,
+    pub total_words: i64,
+    pub total_characters: i64,
+    pub average_words: f64,
+    pub average_characters: f64,
+    /// 今日または昨日まで続いている連続日数
+    pub current_streak: i64,
+    pub longest_streak: i64,
+    /// 0 = 日曜日 〜 6 = 土曜日
+    pub most_active_weekday: Option<i32>,
+    /// 0〜23時
+    pub most_active_hour: Option<i32>,
+    pub entries_per_month: Vec<MonthlyCount>,
+}
+
+/// 本文の差分の1行分
+#[derive(Debug, Serialize)]
+pub struct DiffLine {
+    /// "equal" / "insert" / "delete"
+    pub op: String,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldChange<T> {
+    pub from: T,
+    pub to: T,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevisionDiff {
+    pub from_revision: i32,
+    pub to_revision: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<FieldChange<Option<String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mood: Option<FieldChange<Option<String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mood_intensity: Option<FieldChange<Option<i16>>>,
+    pub images_added: Vec<String>,
+    pub images_removed: Vec<String>,
+    pub content: Vec<DiffLine>,
+}
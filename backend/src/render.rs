@@ -0,0 +1,162 @@
+//! 投稿本文のHTML化
+//!
+//! 本文はサーバーでHTMLにして無害化し、どのクライアントでも同じ表示にする。
+//! - プレーンテキスト: エスケープし、段落と改行を保つ
+//! - Markdown: 生のHTMLは解釈せず文字として表示する
+//! - 無害化: スクリプトやイベント属性を除き、リンクは http(s) / mailto に限る。
+//!   画像は投稿者がアップロードしたもの（`/uploads/...`）だけを残す
+
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag, TagEnd};
+use std::collections::HashSet;
+
+use crate::models::ContentFormat;
+
+/// アップロードしたファイルを配信するURLの接頭辞
+const UPLOADS_PREFIX: &str = "/uploads/";
+
+fn markdown_options() -> Options {
+    Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH
+}
+
+/// 画像のURLがアップロードしたファイルを指していれば、そのファイル名を返す
+fn upload_filename(url: &str) -> Option<&str> {
+    let filename = url.strip_prefix(UPLOADS_PREFIX)?;
+    if filename.is_empty() || filename.contains('/') || filename.contains("..") {
+        return None;
+    }
+    Some(filename)
+}
+
+/// 本文に埋め込まれた画像のうち、アップロードしたファイルのファイル名を重複を除いて返す
+///
+/// 呼び出し側はこれを使って投稿者が所有するファイルを調べる。
+pub fn image_filenames(content: &str, format: ContentFormat) -> Vec<String> {
+    if format != ContentFormat::Markdown {
+        return Vec::new();
+    }
+
+    let mut filenames: Vec<String> = Vec::new();
+    for event in Parser::new_ext(content, markdown_options()) {
+        if let Event::Start(Tag::Image { dest_url, .. }) = event {
+            if let Some(filename) = upload_filename(&dest_url) {
+                if !filenames.iter().any(|f| f == filename) {
+                    filenames.push(filename.to_string());
+                }
+            }
+        }
+    }
+    filenames
+}
+
+/// 本文を無害化したHTMLにする
+///
+/// 画像は `owned_uploads`（投稿者がアップロードしたファイル名）を指すものだけを残す。
+pub fn to_html(content: &str, format: ContentFormat, owned_uploads: &HashSet<String>) -> String {
+    let mut rendered = String::with_capacity(content.len() * 3 / 2);
+    match format {
+        ContentFormat::Plain => html::push_html(&mut rendered, plain_events(content).into_iter()),
+        ContentFormat::Markdown => html::push_html(&mut rendered, markdown_events(content)),
+    }
+    sanitize(&rendered, owned_uploads)
+}
+
+fn plain_events(content: &str) -> Vec<Event<'_>> {
+    let mut events = Vec::new();
+    let normalized = content.replace("\r\n", "\n");
+
+    for paragraph in normalized.split("\n\n").map(|p| p.trim_matches('\n')) {
+        if paragraph.trim().is_empty() {
+            continue;
+        }
+        events.push(Event::Start(Tag::Paragraph));
+        for (i, line) in paragraph.split('\n').enumerate() {
+            if i > 0 {
+                events.push(Event::HardBreak);
+            }
+            events.push(Event::Text(CowStr::from(line.to_string())));
+        }
+        events.push(Event::End(TagEnd::Paragraph));
+    }
+    events
+}
+
+fn markdown_events(content: &str) -> impl Iterator<Item = Event<'_>> {
+    Parser::new_ext(content, markdown_options()).map(|event| match event {
+        // 生のHTMLは解釈せず、書かれたとおりに表示する
+        Event::Html(raw) | Event::InlineHtml(raw) => Event::Text(raw),
+        event => event,
+    })
+}
+
+fn sanitize(html: &str, owned_uploads: &HashSet<String>) -> String {
+    let owned_uploads = owned_uploads.clone();
+
+    ammonia::Builder::default()
+        .url_schemes(HashSet::from(["http", "https", "mailto"]))
+        .attribute_filter(move |element, attribute, value| {
+            if element == "img" && attribute == "src" {
+                return upload_filename(value)
+                    .filter(|filename| owned_uploads.contains(*filename))
+                    .map(|_| value.into());
+            }
+            Some(value.into())
+        })
+        .clean(html)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owned(filenames: &[&str]) -> HashSet<String> {
+        filenames.iter().map(|f| f.to_string()).collect()
+    }
+
+    fn markdown(content: &str) -> String {
+        to_html(content, ContentFormat::Markdown, &owned(&["mine.png"]))
+    }
+
+    #[test]
+    fn script_tags_are_shown_as_text() {
+        let html = markdown("<script>alert(1)</script>");
+        assert!(!html.contains("<script"), "{}", html);
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"), "{}", html);
+    }
+
+    #[test]
+    fn raw_html_is_not_interpreted() {
+        let html = markdown("本文 <img src=x onerror=alert(1)> と <b>太字</b>");
+        assert!(!html.contains("<img"), "{}", html);
+        assert!(!html.contains("<b>"), "{}", html);
+        assert!(html.contains("&lt;b&gt;太字&lt;/b&gt;"), "{}", html);
+    }
+
+    #[test]
+    fn javascript_links_lose_their_href() {
+        let html = markdown("[click](javascript:alert(1)) [ok](https://example.com)");
+        assert!(!html.contains("javascript:"), "{}", html);
+        assert!(html.contains("href=\"https://example.com\""), "{}", html);
+    }
+
+    #[test]
+    fn only_owned_uploads_are_kept_as_images() {
+        let html = markdown("![a](/uploads/mine.png) ![b](/uploads/theirs.png) ![c](https://example.com/x.png)");
+        assert!(html.contains("src=\"/uploads/mine.png\""), "{}", html);
+        assert!(!html.contains("theirs.png"), "{}", html);
+        assert!(!html.contains("example.com"), "{}", html);
+    }
+
+    #[test]
+    fn plain_text_is_escaped_with_breaks() {
+        let html = to_html("1行目 <b>\r\n2行目\n\n\n次の段落", ContentFormat::Plain, &HashSet::new());
+        assert_eq!(html, "<p>1行目 &lt;b&gt;<br>\n2行目</p>\n<p>次の段落</p>\n");
+    }
+
+    #[test]
+    fn image_filenames_skip_foreign_and_nested_paths() {
+        let content = "![](/uploads/a.png) ![](/uploads/a.png) ![](/uploads/../b.png) ![](/uploads/c/d.png) ![](https://x/e.png)";
+        assert_eq!(image_filenames(content, ContentFormat::Markdown), vec!["a.png".to_string()]);
+        assert!(image_filenames(content, ContentFormat::Plain).is_empty());
+    }
+}
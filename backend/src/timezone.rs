@@ -0,0 +1,137 @@
+//! ユーザーのタイムゾーン
+//!
+//! 記録日の絞り込みやカレンダー、連続記録日数は、ユーザーが設定したタイムゾーン
+//! （IANAの名前。未設定ならUTC）の日付で扱う。
+//! - parse / resolve: タイムゾーン名の解釈
+//! - local_to_utc: 現地時刻からUTCへの変換（夏時間の切り替えを含む）
+//! - parse_bound: 日付の絞り込み条件の解釈
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// `Asia/Tokyo` のようなIANAのタイムゾーン名を解釈する
+pub fn parse(name: &str) -> Option<Tz> {
+    name.parse().ok()
+}
+
+/// [`parse`] と同じだが、tzデータベースにない名前はUTCとして扱う
+pub fn resolve(name: &str) -> Tz {
+    parse(name).unwrap_or(Tz::UTC)
+}
+
+/// `tz` での今日の日付
+pub fn today(tz: Tz) -> NaiveDate {
+    Utc::now().with_timezone(&tz).date_naive()
+}
+
+/// `tz` の現地時刻をUTCに変換する
+///
+/// 夏時間の終了で二度ある時刻は早い方、開始で存在しない時刻は1時間後として扱う。
+pub fn local_to_utc(tz: Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    tz.from_local_datetime(&local)
+        .earliest()
+        .or_else(|| tz.from_local_datetime(&(local + Duration::hours(1))).earliest())
+        .map(|dt| dt.with_timezone(&Utc))
+        .unwrap_or_else(|| local.and_utc())
+}
+
+/// 日付の絞り込み条件を解釈する
+///
+/// RFC 3339 の日時はその時差のまま、時差のない日時と日付だけの値は `tz` の現地時刻として読む。
+/// 日付だけの値を上限（`end_of_day`）に使うとその日の終わりまでを含む。
+/// 夏時間の終了で日の終わりが二度ある場合は遅い方までを含める。
+pub fn parse_bound(value: &str, tz: Tz, end_of_day: bool) -> Option<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Some(dt.with_timezone(&Utc));
+    }
+
+    for format in ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M"] {
+        if let Ok(local) = NaiveDateTime::parse_from_str(value, format) {
+            return Some(local_to_utc(tz, local));
+        }
+    }
+
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+    if !end_of_day {
+        return Some(local_to_utc(tz, date.and_time(NaiveTime::MIN)));
+    }
+    let local = date.and_time(NaiveTime::from_hms_micro_opt(23, 59, 59, 999_999)?);
+    Some(
+        tz.from_local_datetime(&local)
+            .latest()
+            .map(|dt| dt.with_timezone(&Utc))
+            .unwrap_or_else(|| local_to_utc(tz, local)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn resolve_falls_back_to_utc() {
+        assert_eq!(resolve("Asia/Tokyo"), chrono_tz::Asia::Tokyo);
+        assert_eq!(resolve("Mars/Olympus_Mons"), Tz::UTC);
+        assert!(parse("Mars/Olympus_Mons").is_none());
+    }
+
+    #[test]
+    fn bare_dates_cover_the_local_day() {
+        let tz = chrono_tz::Asia::Tokyo;
+        assert_eq!(parse_bound("2024-03-10", tz, false), Some(utc("2024-03-09T15:00:00Z")));
+        assert_eq!(parse_bound("2024-03-10", tz, true), Some(utc("2024-03-10T14:59:59.999999Z")));
+    }
+
+    #[test]
+    fn rfc3339_keeps_its_offset() {
+        let tz = chrono_tz::Asia::Tokyo;
+        assert_eq!(parse_bound("2024-03-10T00:00:00Z", tz, false), Some(utc("2024-03-10T00:00:00Z")));
+        assert_eq!(parse_bound("2024-03-10T09:00:00+09:00", tz, true), Some(utc("2024-03-10T00:00:00Z")));
+    }
+
+    #[test]
+    fn naive_timestamps_are_local() {
+        let tz = chrono_tz::America::New_York;
+        assert_eq!(parse_bound("2024-01-15T09:30", tz, false), Some(utc("2024-01-15T14:30:00Z")));
+        assert_eq!(parse_bound("2024-07-15 09:30:00", tz, false), Some(utc("2024-07-15T13:30:00Z")));
+    }
+
+    #[test]
+    fn skipped_times_move_forward() {
+        // 2024-03-10 02:00 に 03:00 へ進む
+        let tz = chrono_tz::America::New_York;
+        assert_eq!(parse_bound("2024-03-10T02:30:00", tz, false), Some(utc("2024-03-10T07:30:00Z")));
+    }
+
+    #[test]
+    fn repeated_times_take_the_earlier_instant() {
+        // 2024-11-03 02:00 に 01:00 へ戻る
+        let tz = chrono_tz::America::New_York;
+        assert_eq!(parse_bound("2024-11-03T01:30:00", tz, false), Some(utc("2024-11-03T05:30:00Z")));
+    }
+
+    #[test]
+    fn day_starting_in_a_gap_begins_after_the_jump() {
+        // サンティアゴでは 2024-09-08 00:00 に 01:00 へ進む
+        let tz = chrono_tz::America::Santiago;
+        assert_eq!(parse_bound("2024-09-08", tz, false), Some(utc("2024-09-08T04:00:00Z")));
+    }
+
+    #[test]
+    fn repeated_end_of_day_includes_the_later_hour() {
+        // サンティアゴでは 2024-04-07 00:00 に 2024-04-06 23:00 へ戻る
+        let tz = chrono_tz::America::Santiago;
+        assert_eq!(parse_bound("2024-04-06", tz, true), Some(utc("2024-04-07T03:59:59.999999Z")));
+        assert_eq!(parse_bound("2024-04-07", tz, false), Some(utc("2024-04-07T04:00:00Z")));
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        assert_eq!(parse_bound("yesterday", Tz::UTC, false), None);
+        assert_eq!(parse_bound("2024-02-30", Tz::UTC, true), None);
+    }
+}
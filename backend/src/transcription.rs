@@ -0,0 +1,125 @@
+//! 音声の文字起こし
+//!
+//! 音声メモから日記を作成するための文字起こしプロバイダを提供。
+//! - OpenAiTranscriber: OpenAI互換API（OpenAI / whisper-server など）
+//! - StubTranscriber: 開発用のスタブ（外部APIを呼び出さない）
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 文字起こしプロバイダ
+#[async_trait]
+pub trait TranscriptionProvider: Send + Sync {
+    /// プロバイダ名（ログ・レスポンス用）
+    fn name(&self) -> &str;
+
+    /// 音声データを文字起こしする
+    async fn transcribe(&self, audio: Vec<u8>, filename: &str, mime_type: &str) -> Result<String, anyhow::Error>;
+}
+
+/// OpenAI互換の `/v1/audio/transcriptions` を呼び出すプロバイダ
+pub struct OpenAiTranscriber {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: String,
+    model: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TranscriptionResponse {
+    text: String,
+}
+
+impl OpenAiTranscriber {
+    /// `timeout` はリクエスト全体（アップロードと文字起こし）の待ち時間の上限
+    pub fn new(base_url: String, api_key: String, model: String, timeout: Duration) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .expect("HTTP client configuration is valid");
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            model,
+        }
+    }
+}
+
+#[async_trait]
+impl TranscriptionProvider for OpenAiTranscriber {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    async fn transcribe(&self, audio: Vec<u8>, filename: &str, mime_type: &str) -> Result<String, anyhow::Error> {
+        let file = reqwest::multipart::Part::bytes(audio)
+            .file_name(filename.to_string())
+            .mime_str(mime_type)?;
+        let form = reqwest::multipart::Form::new()
+            .part("file", file)
+            .text("model", self.model.clone())
+            .text("response_format", "json");
+
+        let mut request = self
+            .client
+            .post(format!("{}/v1/audio/transcriptions", self.base_url))
+            .multipart(form);
+        if !self.api_key.is_empty() {
+            request = request.bearer_auth(&self.api_key);
+        }
+
+        let response = request.send().await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            tracing::error!("Transcription API error: {}", error_text);
+            return Err(anyhow::anyhow!("文字起こしでエラーが発生しました"));
+        }
+
+        let result: TranscriptionResponse = response.json().await?;
+        Ok(result.text.trim().to_string())
+    }
+}
+
+/// 外部APIを呼び出さない開発用プロバイダ
+pub struct StubTranscriber;
+
+#[async_trait]
+impl TranscriptionProvider for StubTranscriber {
+    fn name(&self) -> &str {
+        "stub"
+    }
+
+    async fn transcribe(&self, audio: Vec<u8>, filename: &str, _mime_type: &str) -> Result<String, anyhow::Error> {
+        Ok(format!(
+            "（文字起こしプロバイダが設定されていません: {}, {} bytes）",
+            filename,
+            audio.len()
+        ))
+    }
+}
+
+/// 環境変数からプロバイダを構築する
+///
+/// `TRANSCRIPTION_PROVIDER=openai` の場合は OpenAI互換API を使用し、
+/// それ以外はスタブを使用する。タイムアウトは `TRANSCRIPTION_TIMEOUT_SECS`（既定120秒）。
+pub fn from_env() -> Arc<dyn TranscriptionProvider> {
+    match std::env::var("TRANSCRIPTION_PROVIDER").as_deref() {
+        Ok("openai") => Arc::new(OpenAiTranscriber::new(
+            std::env::var("TRANSCRIPTION_API_URL").unwrap_or_else(|_| "https://api.openai.com".to_string()),
+            std::env::var("TRANSCRIPTION_API_KEY").unwrap_or_default(),
+            std::env::var("TRANSCRIPTION_MODEL").unwrap_or_else(|_| "whisper-1".to_string()),
+            Duration::from_secs(
+                std::env::var("TRANSCRIPTION_TIMEOUT_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .filter(|&secs: &u64| secs > 0)
+                    .unwrap_or(120),
+            ),
+        )),
+        _ => Arc::new(StubTranscriber),
+    }
+}
@@ -91,7 +91,7 @@ export default function PostDetailPage() {
               </CardTitle>
               <div className="flex items-center text-sm text-muted-foreground">
                 <Calendar className="h-4 w-4 mr-1" />
-                {new Date(post.created_at).toLocaleDateString()}
+                {new Date(post.entry_date).toLocaleDateString()}
               </div>
             </div>
             <div className="flex gap-2">
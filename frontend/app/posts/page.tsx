@@ -76,7 +76,7 @@ export default function PostsPage() {
     return null;
   }
 
-  const totalPages = data ? Math.ceil(data.total / data.per_page) : 0;
+  const totalPages = data ? Math.ceil((data.total ?? 0) / data.per_page) : 0;
 
   return (
     <div className="container mx-auto px-4 py-8">
@@ -56,7 +56,7 @@ export function PostCard({ post }: PostCardProps) {
       <CardFooter className="flex items-center justify-between pt-0">
         <div className="flex items-center text-xs text-muted-foreground">
           <Calendar className="h-3 w-3 mr-1" />
-          {new Date(post.created_at).toLocaleDateString()}
+          {new Date(post.entry_date).toLocaleDateString()}
         </div>
         <div className="flex gap-1">
           <Link href={`/posts/${post.id}/edit`}>
@@ -161,7 +161,7 @@ export function PostForm({ post, isEditing = false, prefillTitle = '', prefillCo
 
     try {
       if (isEditing && post) {
-        await updatePost.mutateAsync({ id: post.id, data });
+        await updatePost.mutateAsync({ id: post.id, data, version: post.version });
         router.push(`/posts/${post.id}`);
       } else {
         const newPost = await createPost.mutateAsync(data as PostCreate);
@@ -41,8 +41,9 @@ export function useUpdatePost() {
   const queryClient = useQueryClient();
 
   return useMutation({
-    mutationFn: ({ id, data }: { id: string; data: PostUpdate }) =>
-      api.put<Post>(`/api/v1/posts/${id}`, data),
+    // version は取得時の投稿のバージョン。他の端末で更新されていれば 412 になる
+    mutationFn: ({ id, data, version }: { id: string; data: PostUpdate; version: number }) =>
+      api.put<Post>(`/api/v1/posts/${id}`, data, { 'If-Match': `"${version}"` }),
     onSuccess: (_, variables) => {
       queryClient.invalidateQueries({ queryKey: ['posts'] });
       queryClient.invalidateQueries({ queryKey: ['post', variables.id] });
@@ -68,10 +68,11 @@ class ApiClient {
     });
   }
 
-  put<T>(endpoint: string, data?: unknown) {
+  put<T>(endpoint: string, data?: unknown, headers?: Record<string, string>) {
     return this.request<T>(endpoint, {
       method: 'PUT',
       body: data ? JSON.stringify(data) : undefined,
+      headers,
     });
   }
 
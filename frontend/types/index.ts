@@ -24,13 +24,46 @@ export interface AuthToken {
 }
 
 // Post types
+export type Mood = 'great' | 'good' | 'neutral' | 'bad' | 'awful';
+
+export type ContentFormat = 'plain' | 'markdown';
+
+export interface PostEncryption {
+  algorithm: 'aes-256-gcm' | 'xchacha20-poly1305';
+  content_nonce: string;
+  title_nonce?: string | null;
+}
+
+export interface EncryptionKey {
+  wrapped_key: string;
+  wrap_algorithm: string;
+  wrap_nonce: string;
+  kdf: 'argon2id' | 'pbkdf2-sha256';
+  kdf_salt: string;
+  kdf_params: Record<string, unknown>;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface EncryptionSettings {
+  enabled: boolean;
+  key: EncryptionKey | null;
+}
+
 export interface Post {
   id: string;
   user_id: string;
   title: string | null;
   content: string;
   mood: string | null;
+  mood_intensity: number | null;
   image_urls: string[];
+  content_format: ContentFormat;
+  encryption?: PostEncryption;
+  status: 'draft' | 'published';
+  entry_date: string;
+  notebook_id: string | null;
+  version: number;
   created_at: string;
   updated_at: string;
 }
@@ -38,29 +71,45 @@ export interface Post {
 export interface PostCreate {
   title?: string;
   content: string;
-  mood?: string;
+  mood?: Mood;
+  mood_intensity?: number;
   image_urls?: string[];
+  content_format?: ContentFormat;
+  entry_date?: string;
+  encryption?: PostEncryption;
 }
 
 export interface PostUpdate {
   title?: string;
   content?: string;
-  mood?: string;
+  mood?: Mood;
+  mood_intensity?: number;
   image_urls?: string[];
+  content_format?: ContentFormat;
+  entry_date?: string;
+  encryption?: PostEncryption;
+}
+
+export interface RenderedPost {
+  id: string;
+  content_format: ContentFormat;
+  rendered_html: string;
+  version: number;
 }
 
 export interface PostFilters {
   search?: string;
-  mood?: string;
+  mood?: Mood;
   date_from?: string;
   date_to?: string;
 }
 
 export interface PostListResponse {
   posts: Post[];
-  total: number;
-  page: number;
+  total?: number;
+  page?: number;
   per_page: number;
+  next_cursor?: string;
 }
 
 // Analysis types